@@ -10,6 +10,8 @@ use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::{self, UnboundedReceiver};
 use wgpu::Backends;
 
+use crate::ipc::OutputPlacement;
+
 #[derive(Deserialize, Debug, Clone, Copy)]
 #[serde(try_from = "String")]
 pub struct ColorConfig(Color32);
@@ -105,7 +107,7 @@ impl Default for ItemConfig {
         }
     }
 }
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum RenderBackend {
     Default,
     Vulkan,
@@ -113,6 +115,17 @@ pub enum RenderBackend {
     Software,
 }
 
+/// How minimized windows are shown in the switcher
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MinimizedWindows {
+    /// In the usual most recently used order
+    #[default]
+    Show,
+    /// After all other windows
+    Last,
+    Hide,
+}
+
 impl Default for RenderBackend {
     fn default() -> Self {
         RenderBackend::Software
@@ -147,6 +160,12 @@ impl Into<Backends> for RenderBackend {
 pub struct Config {
     /// Set to `Software`, `Gl`, or `Vulkan`
     pub render_backend: RenderBackend,
+    /// Set to `auto`, `focused-window`, `pointer`, `all` or the name of an output
+    pub output: OutputPlacement,
+    /// Set to `Show`, `Last` or `Hide`
+    pub minimized_windows: MinimizedWindows,
+    /// Name of the seat to use on multi-seat setups, all seats are used if unset
+    pub seat: Option<String>,
     pub window: WindowConfig,
     pub item: ItemConfig,
 }
@@ -267,22 +286,28 @@ impl ConfigHandle {
         &self.config
     }
 
+    /// Re-reads the config file, keeping the current config if it cannot be parsed
+    pub fn reload(&mut self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            // the config file could not be used at startup, try again from scratch
+            let (config, path) = Self::get_existing_config()?;
+            self.config = config;
+            self.path = path.into();
+            return Ok(());
+        };
+        let config_str = fs::read_to_string(path)?;
+
+        self.config = toml::from_str::<Config>(&config_str)?;
+        Ok(())
+    }
+
     pub async fn recv(&mut self) -> Option<ConfigEvent> {
         while let Some(event) = self.event_rx.recv().await {
             match event {
-                ConfigEvent::Updated => {
-                    if let Some(path) = &self.path
-                        && let Ok(config_str) = fs::read_to_string(&path)
-                    {
-                        match toml::from_str::<Config>(&config_str) {
-                            Ok(new_config) => {
-                                self.config = new_config;
-                                return Some(event);
-                            }
-                            Err(err) => tracing::warn!("Failed to parsed updated config: {}", err),
-                        }
-                    }
-                }
+                ConfigEvent::Updated => match self.reload() {
+                    Ok(()) => return Some(event),
+                    Err(err) => tracing::warn!("Failed to parsed updated config: {}", err),
+                },
             }
         }
         None
@@ -1,7 +1,12 @@
-use std::{mem, time::Duration};
+use std::{
+    io, mem,
+    time::{Duration, Instant},
+};
 
-use anyhow::Context;
-use smithay_client_toolkit::reexports::client::{EventQueue, protocol::wl_shm::Format};
+use anyhow::{Context, Result};
+use smithay_client_toolkit::reexports::client::{
+    EventQueue, backend::WaylandError, protocol::wl_output::WlOutput,
+};
 use tokio::{
     io::unix::AsyncFd,
     sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
@@ -12,12 +17,20 @@ use crate::{
     config_worker::{ConfigEvent, ConfigHandle, RenderBackend},
     geometry_worker::{GeometryWorker, GeometryWorkerEvent},
     gui::{Gui, GuiEvent},
+    gui_state::Item,
     image_resizer::ImageResizer,
-    ipc::{AlttabwayIpc, Direction, IpcCommand, Modifier},
+    ipc::{
+        AlttabwayIpc, DaemonStatus, Direction, IpcCommand, IpcCommandResponse, IpcEvent,
+        IpcRequest, IpcServer, Modifier, OutputPlacement, OutputStatus, PreviewImage, WindowInfo,
+        WindowSelector, WindowStatus,
+    },
+    pixel_format::{self, FrameLayout},
     renderer::{Renderer, SoftwareRenderer, WgpuRenderer},
+    stitch::{RgbPiece, stitch},
     timer::Timer,
     wayland_client::WaylandClient,
-    wayland_client_event::WaylandClientEvent,
+    wayland_client_event::{CapturePiece, WaylandClientEvent},
+    window_matcher::WindowMatcher,
 };
 
 pub struct Daemon {
@@ -38,35 +51,81 @@ pub struct Daemon {
 
     geometry_worker: GeometryWorker<u32>,
 
-    ipc_listener: UnboundedReceiver<IpcCommand>,
+    ipc_server: IpcServer,
     visible: bool,
 
+    /// Last selection sent to IPC subscribers while the switcher is visible
+    published_selection: Option<u32>,
+
     screenshot_timer: Timer,
 
     /// Modifier keys that are required to be pressed for the window to show
     required_modifiers: Vec<Modifier>,
+    /// Ignore modifier releases, the switcher stays open until confirmed or cancelled
+    sticky: bool,
+    /// Where the switcher is shown, from `show --output` or the config
+    output_placement: OutputPlacement,
 
     config_handle: ConfigHandle,
+    render_backend: RenderBackend,
+
+    /// Set while the connection to the compositor is lost and we are trying to reconnect
+    reconnect: Option<Reconnect>,
+}
+
+/// Backoff state of the attempts to reconnect to the compositor
+struct Reconnect {
+    started: Instant,
+    delay: Duration,
+    next_attempt: tokio::time::Instant,
+}
+
+impl Reconnect {
+    const INITIAL_DELAY: Duration = Duration::from_millis(250);
+    const MAX_DELAY: Duration = Duration::from_secs(5);
+    const GIVE_UP_AFTER: Duration = Duration::from_secs(60);
+
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            delay: Self::INITIAL_DELAY,
+            next_attempt: tokio::time::Instant::now() + Self::INITIAL_DELAY,
+        }
+    }
+
+    /// Schedules the next attempt, or returns false if we should give up
+    fn backoff(&mut self) -> bool {
+        if self.started.elapsed() >= Self::GIVE_UP_AFTER {
+            return false;
+        }
+        self.delay = (self.delay * 2).min(Self::MAX_DELAY);
+        self.next_attempt = tokio::time::Instant::now() + self.delay;
+        true
+    }
 }
 
 impl Daemon {
     pub const DEFAULT_REQ_MODIFIER: [Modifier; 1] = [Modifier::Alt];
 
-    pub async fn start() -> anyhow::Result<()> {
+    pub async fn start(ipc: &AlttabwayIpc, replace: bool) -> anyhow::Result<()> {
         // IPC Listener makes sure that this is the only instance running
-        let ipc_listener = AlttabwayIpc::start_server().await?;
+        let ipc_server = ipc.start_server(replace).await?;
         let config_handle = ConfigHandle::new();
-        let geometry_worker = GeometryWorker::new()?;
+        let geometry_worker = GeometryWorker::new();
 
         let (wayland_client, wayland_client_q, wayland_client_rx) = WaylandClient::init()?;
 
+        if !wayland_client.can_capture_toplevels() && geometry_worker.provider_name().is_none() {
+            tracing::warn!(
+                "The compositor supports neither ext-image-copy-capture nor a known IPC, window previews are unavailable"
+            );
+        }
+
         let (renderer_tx, renderer_rx) = mpsc::unbounded_channel();
 
         let preview_resizer = ImageResizer::new();
-        let renderer: Box<dyn Renderer> = match config_handle.get_config().render_backend {
-            RenderBackend::Software => Box::new(SoftwareRenderer::new()),
-            backends => Box::new(WgpuRenderer::new(backends).await?),
-        };
+        let render_backend = config_handle.get_config().render_backend;
+        let renderer = Self::create_renderer(render_backend).await?;
 
         debug!("Initialized wayland layer client");
 
@@ -83,38 +142,77 @@ impl Daemon {
             gui: Gui::new(config_handle.get_config()),
             pending_repaint: false,
             geometry_worker,
-            ipc_listener,
+            ipc_server,
             visible: false,
+            published_selection: None,
             screenshot_timer: Timer::new(Duration::from_secs(5)),
             required_modifiers: Self::DEFAULT_REQ_MODIFIER.to_vec(),
+            sticky: false,
+            output_placement: config_handle.get_config().output.clone(),
             config_handle,
+            render_backend,
+            reconnect: None,
         };
+        daemon
+            .gui
+            .set_scale(daemon.wayland_client.get_surface_scale() as f32);
+        daemon
+            .wayland_client
+            .set_pinned_seat(daemon.config_handle.get_config().seat.clone());
 
         Daemon::run_loop(&mut daemon).await
     }
 
     async fn run_loop(&mut self) -> anyhow::Result<()> {
         loop {
-            self.wayland_client_q.flush()?;
+            // there's no connection to read from while reconnecting, but everything else is
+            // still serviced
+            let mut read_guard = None;
+            let mut flush_blocked = false;
+            if self.reconnect.is_none() {
+                match self.wayland_client_q.flush() {
+                    Ok(()) => (),
+                    // the socket buffer is full, flush again once the compositor caught up
+                    Err(WaylandError::Io(err)) if err.kind() == io::ErrorKind::WouldBlock => {
+                        flush_blocked = true
+                    }
+                    Err(err) => {
+                        self.connection_lost(err.into())?;
+                        continue;
+                    }
+                }
 
-            let Some(read_guard) = self.wayland_client_q.prepare_read() else {
-                self.wayland_client_q
-                    .dispatch_pending(&mut self.wayland_client)?;
-                continue;
-            };
+                let Some(guard) = self.wayland_client_q.prepare_read() else {
+                    self.dispatch_wayland_events()?;
+                    continue;
+                };
+                read_guard = Some(guard);
+            }
 
-            let async_fd = AsyncFd::new(read_guard.connection_fd())?;
+            let async_fd = read_guard
+                .as_ref()
+                .map(|guard| AsyncFd::new(guard.connection_fd()))
+                .transpose()?;
+            let next_attempt = self
+                .reconnect
+                .as_ref()
+                .map(|reconnect| reconnect.next_attempt);
 
             tokio::select! {
-                _ = async_fd.readable() => {
+                _ = async { async_fd.as_ref()?.readable().await.ok() }, if async_fd.is_some() => {
                     drop(async_fd);
 
-                    read_guard.read()?;
-
-                    self.wayland_client_q
-                        .dispatch_pending(&mut self.wayland_client)?;
+                    match read_guard.take().context("missing wayland read guard")?.read() {
+                        Ok(_) => self.dispatch_wayland_events()?,
+                        Err(WaylandError::Io(err)) if err.kind() == io::ErrorKind::WouldBlock => (),
+                        Err(err) => self.connection_lost(err.into())?,
+                    }
+                },
+                _ = async { async_fd.as_ref()?.writable().await.ok() }, if flush_blocked => (),
+                _ = tokio::time::sleep_until(next_attempt.unwrap_or_else(tokio::time::Instant::now)), if next_attempt.is_some() => {
+                    self.try_reconnect()?;
                 },
-                result = self.wayland_client_rx.recv() => {
+                result = self.wayland_client_rx.recv(), if self.reconnect.is_none() => {
                     let event = result.context("wayland client has crashed")?;
                     trace!("received wayland client event {:?}", event);
 
@@ -128,10 +226,32 @@ impl Daemon {
                                 continue;
                             }
 
-                            self.renderer.init_surface(&mut self.wayland_client, self.width, self.height, self.renderer_tx.clone())?;
+                            // resized or another mirrored surface became ready, the renderer
+                            // can't have two swapchains for the same surface
+                            self.renderer.destroy_surface(&mut self.wayland_client)?;
+                            self.init_renderer_surface()?;
+                        }
+                        WaylandClientEvent::ScaleChanged(scale) => {
+                            self.gui.set_scale(scale as f32);
+
+                            if !self.visible || !self.wayland_client.has_surfaces() {
+                                continue;
+                            }
+
+                            // the surface needs buffers of a different size
+                            self.renderer.destroy_surface(&mut self.wayland_client)?;
+                            self.wayland_client.apply_surface_scale();
+                            self.init_renderer_surface()?;
                         }
                         WaylandClientEvent::Egui(events) => {
                             self.gui.handle_events(events);
+                            self.publish_selection();
+
+                            // searching changes which windows are shown
+                            let (width, height) = self.gui.get_window_dimensions();
+                            if self.visible && self.wayland_client.has_surfaces() && (width, height) != (self.width, self.height) {
+                                self.wayland_client.resize_surfaces(width, height);
+                            }
 
                             if self.gui.needs_repaint() {
                                 self.request_repaint()?
@@ -139,6 +259,10 @@ impl Daemon {
                         }
                         WaylandClientEvent::PaintRequest => self.paint()?,
                         WaylandClientEvent::ModifierChange => {
+                            if self.sticky {
+                                continue;
+                            }
+
                             let wl_modifiers = self.wayland_client.get_modifiers();
 
                             if !wl_modifiers.ctrl && self.required_modifiers.contains(&Modifier::Ctrl) ||
@@ -147,18 +271,21 @@ impl Daemon {
                                !wl_modifiers.logo && self.required_modifiers.contains(&Modifier::Super)
                             {
                                 if self.visible {
-                                    self.update_visibility(false)?;
-
-                                    if let Some(window_id) = self.gui.get_selected_item_id() {
-                                        self.wayland_client.activate_window(window_id);
-                                    }
+                                    self.confirm_selection()?;
                                 }
                             }
                         }
-                        WaylandClientEvent::TopLevelAdded(id) => self.gui.add_item(id),
+                        WaylandClientEvent::TopLevelAdded(id) => {
+                            self.gui.add_item(id);
+                            self.ipc_server.publish(IpcEvent::WindowAdded { id });
+                        }
                         WaylandClientEvent::TopLevelActivated(id) => {
                             self.gui.signal_item_activation(id);
 
+                            if let Some(item) = self.gui.get_items().first() {
+                                self.ipc_server.publish(IpcEvent::WindowActivated { window: Self::to_window_info(item) });
+                            }
+
                             // take screenshot for preview
                             if self.visible && self.wayland_client.has_surfaces() {
                                 continue
@@ -166,23 +293,69 @@ impl Daemon {
 
                             self.screenshot_timer.ping_after(Duration::from_secs(1)).await?;
                         }
-                        WaylandClientEvent::TopLevelTitleUpdate(id, new_title) => self.gui.update_item_title(id, new_title),
+                        WaylandClientEvent::TopLevelTitleUpdate(id, new_title) => {
+                            self.ipc_server.publish(IpcEvent::WindowTitleChanged { id, title: new_title.clone() });
+                            self.gui.update_item_title(id, new_title);
+                        }
                         WaylandClientEvent::TopLevelAppIdUpdate(id, new_app_id) => self.gui.update_item_app_id(id, new_app_id),
-                        WaylandClientEvent::TopLevelRemoved(id) => self.gui.remove_item(id),
-                        WaylandClientEvent::ScreencopyDone(id, buffer, format) => {
+                        WaylandClientEvent::TopLevelStateUpdate(id, state) => self.gui.update_item_state(id, state),
+                        WaylandClientEvent::TopLevelParentUpdate(id, parent) => self.gui.update_item_parent(id, parent),
+                        WaylandClientEvent::TopLevelRemoved(id) => {
+                            self.gui.remove_item(id);
+                            self.ipc_server.publish(IpcEvent::WindowRemoved { id });
+                        }
+                        WaylandClientEvent::ScreencopyDone(id, captured) => {
                             let _span = tracing::trace_span!("Resize", id=id).entered();
                             tracing::trace!("start");
 
-                            let pixels = self.wayland_client.get_buffer_mut(&buffer, |slice| {
-                                match format {
-                                    Format::Argb8888 | Format::Xrgb8888 =>
-                                        slice.chunks(4).flat_map(|p| [p[2], p[1], p[0]]).collect(),
-                                    Format::Bgr888 => slice.to_vec(),
-                                    _ => panic!("unknown format")
+                            let pixels = self.wayland_client.get_buffer_mut(&captured.buffer, |slice| pixel_format::to_rgb(slice, &captured.layout)).flatten();
+                            let FrameLayout { width, height, .. } = captured.layout;
+                            self.wayland_client.recycle_buffer(captured);
+
+                            let pixels = match pixels {
+                                Ok(pixels) => pixels,
+                                Err(err) => {
+                                    tracing::warn!("Could not convert capture of window {}: {}", id, err);
+                                    continue;
                                 }
-                            });
+                            };
+                            self.gui.signal_item_capture(id);
 
-                            let (width, height) = (pixels.len() as u32 / buffer.height() as u32 / 3, buffer.height() as u32);
+                            self.preview_resizer.resize_rgb_pixels(id, (pixels, width), self.gui.calculate_preview_size((width, height)));
+                        }
+                        WaylandClientEvent::ScreencopyRegionsDone(id, size, pieces) => {
+                            let _span = tracing::trace_span!("Stitch", id=id).entered();
+                            tracing::trace!("start");
+
+                            let pieces = pieces.into_iter().map(|CapturePiece { buffer: captured, region, transform }| {
+                                let pixels = self.wayland_client.get_buffer_mut(&captured.buffer, |slice| pixel_format::to_rgb(slice, &captured.layout)).flatten();
+                                let FrameLayout { width, height, .. } = captured.layout;
+                                self.wayland_client.recycle_buffer(captured);
+
+                                anyhow::Ok(RgbPiece {
+                                    width,
+                                    height,
+                                    pixels: pixels?,
+                                    region,
+                                    transform,
+                                })
+                            }).collect::<Result<_>>();
+
+                            let pieces = match pieces {
+                                Ok(pieces) => pieces,
+                                Err(err) => {
+                                    tracing::warn!("Could not convert capture of window {}: {}", id, err);
+                                    continue;
+                                }
+                            };
+
+                            let (pixels, width) = stitch(size, pieces);
+                            if width == 0 {
+                                continue;
+                            }
+                            self.gui.signal_item_capture(id);
+
+                            let height = pixels.len() as u32 / width / 3;
                             self.preview_resizer.resize_rgb_pixels(id, (pixels, width), self.gui.calculate_preview_size((width, height)));
                         }
                     }
@@ -221,44 +394,128 @@ impl Daemon {
 
                             let _ = self.wayland_client.capture_window_region(window_id, x, y, width, height, &self.wayland_client_q.handle());
                         }
+                        GeometryWorkerEvent::CursorPosition(position) => {
+                            if !self.visible || self.wayland_client.has_surfaces() {
+                                continue
+                            }
+
+                            let outputs = self.resolve_outputs(position);
+                            self.create_surfaces(&outputs)?;
+                        }
                     }
                 }
-                result = self.ipc_listener.recv() => {
-                    let event = result.context("ipc server has crashed")?;
-                    tracing::debug!("ipc event: {:?}", event);
-
-                    match event {
-                        IpcCommand::Ping => (),
-                        IpcCommand::Show { direction, mut modifiers } => {
+                result = self.ipc_server.recv() => {
+                    let IpcRequest { command, responder } = result.context("ipc server has crashed")?;
+                    tracing::debug!("ipc event: {:?}", command);
+
+                    let response = match command {
+                        IpcCommand::Ping => IpcCommandResponse::Success,
+                        IpcCommand::Show { .. } if self.reconnect.is_some() => {
+                            IpcCommandResponse::Error("Reconnecting to the compositor".into())
+                        }
+                        IpcCommand::Show { direction, mut modifiers, sticky, output } => {
                             mem::swap(&mut self.required_modifiers, &mut modifiers);
                             if self.visible {
+                                self.sticky |= sticky;
                                 if let Some(direction) = direction {
                                     match direction {
                                         Direction::Previous => self.gui.select_previous_item(),
                                         Direction::Next => self.gui.select_next_item(),
                                     }
+                                    self.publish_selection();
                                     self.request_repaint()?;
                                 }
                             } else {
+                                self.output_placement = output.unwrap_or_else(|| self.config_handle.get_config().output.clone());
                                 self.update_visibility(true)?;
+                                self.sticky = sticky;
                             }
+                            IpcCommandResponse::Success
                         }
-                        IpcCommand::Hide => self.update_visibility(false)?,
-                    }
+                        IpcCommand::Hide | IpcCommand::Cancel => {
+                            self.update_visibility(false)?;
+                            IpcCommandResponse::Success
+                        }
+                        IpcCommand::Confirm if self.visible => {
+                            self.confirm_selection()?;
+                            IpcCommandResponse::Success
+                        }
+                        IpcCommand::Select(index) if self.visible => match self.gui.select_item(index) {
+                            true => {
+                                self.publish_selection();
+                                self.request_repaint()?;
+                                IpcCommandResponse::Success
+                            }
+                            false => IpcCommandResponse::Error(format!("There is no window at position {}", index)),
+                        },
+                        IpcCommand::Confirm | IpcCommand::Select(_) => {
+                            IpcCommandResponse::Error("The switcher is not visible".into())
+                        }
+                        IpcCommand::List => IpcCommandResponse::WindowList(self.get_window_list()),
+                        IpcCommand::Activate(selector) => match self.find_window(&selector) {
+                            Ok(Some(window_id)) => {
+                                if self.visible {
+                                    self.update_visibility(false)?;
+                                }
+                                self.wayland_client.activate_window(self.gui.get_focus_target(window_id));
+                                IpcCommandResponse::Success
+                            }
+                            Ok(None) => IpcCommandResponse::Error("No matching window found".into()),
+                            Err(err) => IpcCommandResponse::Error(format!("Invalid pattern: {}", err)),
+                        },
+                        // handled by the IPC server itself
+                        IpcCommand::Subscribe => IpcCommandResponse::Error("Unexpected subscribe request".into()),
+                        IpcCommand::Quit => {
+                            tracing::info!("Quit requested, exiting");
+                            self.update_visibility(false)?;
+                            self.ipc_server.remove_socket();
+                            responder.respond_and_wait(IpcCommandResponse::Success).await;
+                            return Ok(());
+                        }
+                        IpcCommand::Status => IpcCommandResponse::Status(self.get_status()),
+                        IpcCommand::Preview { id } => match self.get_preview(id) {
+                            Ok(preview) => IpcCommandResponse::Preview(preview),
+                            Err(err) => IpcCommandResponse::Error(err.to_string()),
+                        },
+                        IpcCommand::Reload => match self.config_handle.reload() {
+                            Ok(()) => match self.apply_config().await {
+                                Ok(()) => IpcCommandResponse::Success,
+                                Err(err) => IpcCommandResponse::Error(format!("Failed to apply config: {}", err)),
+                            },
+                            Err(err) => IpcCommandResponse::Error(format!("Failed to reload config: {}", err)),
+                        },
+                    };
+
+                    responder.respond(response);
                 }
                 result = self.screenshot_timer.wait() => {
                     result.context("screenshot timer has unexpectedly crashed")?;
 
                     let Some(active_window_id) = self.get_active_window_id() else { continue };
 
-                    self.geometry_worker.request_active_window_geometry(active_window_id)?;
+                    if self.visible || !self.wayland_client.can_capture_toplevels() {
+                        self.geometry_worker.request_active_window_geometry(active_window_id)?;
+                        continue;
+                    }
+
+                    self.capture_toplevels(active_window_id)?;
                 }
                 Some(event) = self.gui.recv() => {
                     match event {
                         GuiEvent::ItemClicked(window_id) => {
                             if self.visible {
                                 self.update_visibility(false)?;
-                                self.wayland_client.activate_window(window_id);
+                                self.wayland_client.activate_window(self.gui.get_focus_target(window_id));
+                            }
+                        }
+                        GuiEvent::Confirm => {
+                            if self.visible {
+                                self.confirm_selection()?;
+                            }
+                        }
+                        GuiEvent::Cancel => {
+                            if self.visible {
+                                self.update_visibility(false)?;
                             }
                         }
                     }
@@ -266,7 +523,9 @@ impl Daemon {
                 Some(event) = self.config_handle.recv() => {
                     match event {
                         ConfigEvent::Updated => {
-                            self.gui.update_from_config(self.config_handle.get_config());
+                            if let Err(err) = self.apply_config().await {
+                                tracing::warn!("Failed to apply config: {}", err);
+                            }
                         }
                     }
                 }
@@ -274,10 +533,265 @@ impl Daemon {
         }
     }
 
+    async fn create_renderer(render_backend: RenderBackend) -> anyhow::Result<Box<dyn Renderer>> {
+        Ok(match render_backend {
+            RenderBackend::Software => Box::new(SoftwareRenderer::new()),
+            backends => Box::new(WgpuRenderer::new(backends).await?),
+        })
+    }
+
+    /// Dispatches the queued wayland events, reconnecting if the connection was lost
+    fn dispatch_wayland_events(&mut self) -> Result<()> {
+        if let Err(err) = self
+            .wayland_client_q
+            .dispatch_pending(&mut self.wayland_client)
+        {
+            self.connection_lost(err.into())?;
+        }
+        Ok(())
+    }
+
+    /// Forgets everything that belongs to the lost connection and starts trying to reconnect,
+    /// e.g. because the compositor restarted. The windows are announced again by the new
+    /// connection, usually with the same ids.
+    fn connection_lost(&mut self, err: anyhow::Error) -> Result<()> {
+        tracing::warn!("Lost the connection to the compositor: {:#}", err);
+
+        // the surfaces belong to the old connection, there's nothing left to destroy them on
+        if self.visible {
+            self.ipc_server.publish(IpcEvent::SwitcherHidden);
+        }
+        self.visible = false;
+        self.sticky = false;
+        self.renderer.destroy_surface(&mut self.wayland_client)?;
+
+        let window_ids: Vec<_> = self.gui.get_items().iter().map(|item| item.id).collect();
+        for id in window_ids {
+            self.gui.remove_item(id);
+            self.ipc_server.publish(IpcEvent::WindowRemoved { id });
+        }
+
+        // results still in flight belong to windows of the old connection, whose ids are
+        // likely reused by the new one
+        self.preview_resizer = ImageResizer::new();
+        self.geometry_worker = GeometryWorker::new();
+
+        self.reconnect = Some(Reconnect::new());
+        Ok(())
+    }
+
+    fn try_reconnect(&mut self) -> Result<()> {
+        let Some(reconnect) = self.reconnect.as_mut() else {
+            return Ok(());
+        };
+
+        let (wayland_client, wayland_client_q, wayland_client_rx) = match WaylandClient::init() {
+            Ok(client) => client,
+            Err(err) if reconnect.backoff() => {
+                debug!("Could not reconnect to the compositor: {:#}", err);
+                return Ok(());
+            }
+            Err(err) => return Err(err.context("could not reconnect to the compositor")),
+        };
+
+        self.reconnect = None;
+        self.wayland_client = wayland_client;
+        self.wayland_client_q = wayland_client_q;
+        self.wayland_client_rx = wayland_client_rx;
+        self.gui
+            .set_scale(self.wayland_client.get_surface_scale() as f32);
+        self.wayland_client
+            .set_pinned_seat(self.config_handle.get_config().seat.clone());
+
+        tracing::info!("Reconnected to the compositor");
+        Ok(())
+    }
+
+    /// Applies the current config, rebuilding the renderer if the backend has changed
+    async fn apply_config(&mut self) -> anyhow::Result<()> {
+        self.gui.update_from_config(self.config_handle.get_config());
+        self.wayland_client
+            .set_pinned_seat(self.config_handle.get_config().seat.clone());
+
+        let render_backend = self.config_handle.get_config().render_backend;
+        if render_backend != self.render_backend {
+            tracing::info!("Switching render backend to {:?}", render_backend);
+
+            // the surfaces are recreated for the new renderer the next time we are shown
+            self.update_visibility(false)?;
+            self.renderer = Self::create_renderer(render_backend).await?;
+            self.render_backend = render_backend;
+        }
+
+        Ok(())
+    }
+
+    /// Captures the active window and every window that doesn't have a preview yet. Falls back
+    /// to capturing the region of the active window if it can't be captured directly.
+    fn capture_toplevels(&mut self, active_window_id: u32) -> anyhow::Result<()> {
+        let window_ids: Vec<_> = self
+            .gui
+            .get_items()
+            .iter()
+            .filter(|item| item.id == active_window_id || item.get_preview().is_none())
+            .map(|item| item.id)
+            .collect();
+
+        for window_id in window_ids {
+            if let Err(err) = self
+                .wayland_client
+                .capture_toplevel(window_id, &self.wayland_client_q.handle())
+            {
+                tracing::debug!("Could not capture window {} directly: {}", window_id, err);
+
+                if window_id == active_window_id {
+                    self.geometry_worker
+                        .request_active_window_geometry(active_window_id)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     fn get_active_window_id(&self) -> Option<u32> {
         self.gui.get_first_item_id()
     }
 
+    fn to_window_info(item: &Item) -> WindowInfo {
+        WindowInfo {
+            id: item.id,
+            title: item.get_raw_title().into(),
+            app_id: item.get_app_id().into(),
+            has_preview: item.get_preview().is_some(),
+            parent: item.get_parent(),
+        }
+    }
+
+    fn get_window_list(&self) -> Vec<WindowInfo> {
+        self.gui
+            .get_items()
+            .iter()
+            .map(Self::to_window_info)
+            .collect()
+    }
+
+    fn get_status(&self) -> DaemonStatus {
+        let outputs = self
+            .wayland_client
+            .get_output_infos()
+            .into_iter()
+            .map(|info| OutputStatus {
+                mode_size: info
+                    .modes
+                    .iter()
+                    .find(|mode| mode.current)
+                    .map(|mode| mode.dimensions),
+                name: info.name,
+                scale_factor: info.scale_factor,
+                logical_position: info.logical_position,
+                logical_size: info.logical_size,
+            })
+            .collect();
+
+        let windows: Vec<_> = self
+            .gui
+            .get_items()
+            .iter()
+            .map(|item| WindowStatus {
+                id: item.id,
+                app_id: item.get_app_id().into(),
+                preview_size: item
+                    .get_preview()
+                    .as_ref()
+                    .map(|(_, [width, height])| (*width as u32, *height as u32)),
+                last_capture_ms: item
+                    .get_last_capture()
+                    .map(|instant| instant.elapsed().as_millis() as u64),
+            })
+            .collect();
+
+        // previews are kept as RGB pixels and uploaded as RGBA textures
+        let preview_bytes = windows
+            .iter()
+            .filter_map(|window| window.preview_size)
+            .map(|(width, height)| width as u64 * height as u64 * (3 + 4))
+            .sum();
+
+        DaemonStatus {
+            version: env!("CARGO_PKG_VERSION").into(),
+            geometry_provider: self.geometry_worker.provider_name().map(Into::into),
+            renderer: self.renderer.describe(),
+            protocols: self.wayland_client.get_protocol_versions(),
+            outputs,
+            windows,
+            preview_bytes,
+            shm_pool_bytes: self.wayland_client.get_pool_size() as u64,
+        }
+    }
+
+    fn get_preview(&self, id: u32) -> anyhow::Result<PreviewImage> {
+        let item = self
+            .gui
+            .get_items()
+            .iter()
+            .find(|item| item.id == id)
+            .context("No window with this id")?;
+
+        let (rgb, [width, height]) = item
+            .get_preview_rgb()
+            .context("This window does not have a preview yet")?;
+
+        Ok(PreviewImage {
+            width: width as u32,
+            height: height as u32,
+            rgb: rgb.to_vec(),
+        })
+    }
+
+    fn publish_selection(&mut self) {
+        let selection = self.gui.get_selected_item_id();
+        if !self.visible || selection == self.published_selection {
+            return;
+        }
+
+        self.published_selection = selection;
+        if let Some(id) = selection {
+            self.ipc_server.publish(IpcEvent::SelectionChanged { id });
+        }
+    }
+
+    /// Finds the most recently used window matching the selector. The focused window is only
+    /// picked if nothing else matches, so that repeated activations cycle through all matches.
+    fn find_window(&self, selector: &WindowSelector) -> anyhow::Result<Option<u32>> {
+        let matcher = WindowMatcher::new(selector)?;
+
+        let mut matching_ids = self
+            .gui
+            .get_items()
+            .iter()
+            .filter(|item| matcher.matches(item.id, item.get_app_id(), item.get_raw_title()))
+            .map(|item| item.id);
+
+        let first_match = matching_ids.next();
+        if first_match.is_some() && first_match == self.get_active_window_id() {
+            return Ok(matching_ids.next().or(first_match));
+        }
+        Ok(first_match)
+    }
+
+    /// Hides the switcher and activates the selected window
+    fn confirm_selection(&mut self) -> anyhow::Result<()> {
+        self.update_visibility(false)?;
+
+        if let Some(window_id) = self.gui.get_selected_item_id() {
+            self.wayland_client
+                .activate_window(self.gui.get_focus_target(window_id));
+        }
+
+        Ok(())
+    }
+
     fn request_repaint(&mut self) -> anyhow::Result<()> {
         if self.pending_repaint {
             return Ok(());
@@ -292,6 +806,12 @@ impl Daemon {
     }
 
     fn update_visibility(&mut self, visible: bool) -> anyhow::Result<()> {
+        if self.visible != visible {
+            self.ipc_server.publish(match visible {
+                true => IpcEvent::SwitcherShown,
+                false => IpcEvent::SwitcherHidden,
+            });
+        }
         self.visible = visible;
 
         if visible {
@@ -300,30 +820,86 @@ impl Daemon {
                 return Ok(());
             }
             tracing::trace!("VISIBILITY CALLED");
+            self.gui.clear_search();
             self.gui.reset_selected_item();
+            self.published_selection = None;
 
-            if self.config_handle.requires_monitor_width()
-                && let Some(monitor_width) = self.wayland_client.get_monitor_width()
+            // the surfaces are created once the compositor has told us where the cursor is
+            if self.output_placement == OutputPlacement::Pointer
+                && self.geometry_worker.provider_name().is_some()
             {
-                tracing::trace!("Monitor width: {}", monitor_width);
-                self.gui.set_monitor_width(monitor_width);
+                return self.geometry_worker.request_cursor_position();
             }
-            (self.width, self.height) = self.gui.get_window_dimensions();
-
-            self.wayland_client.create_surfaces(
-                &self.wayland_client_q.handle(),
-                self.width,
-                self.height,
-            )?;
-            tracing::trace!("SURFACES CREATED");
+
+            let outputs = self.resolve_outputs(None);
+            self.create_surfaces(&outputs)?;
         } else {
+            self.sticky = false;
             self.renderer.destroy_surface(&mut self.wayland_client)?;
             self.wayland_client.destroy_surfaces();
+            self.wayland_client.trim_pool()?;
         }
 
         Ok(())
     }
 
+    fn create_surfaces(&mut self, outputs: &[WlOutput]) -> anyhow::Result<()> {
+        if self.config_handle.requires_monitor_width()
+            && let Some(monitor_width) = self.wayland_client.get_monitor_width()
+        {
+            tracing::trace!("Monitor width: {}", monitor_width);
+            self.gui.set_monitor_width(monitor_width);
+        }
+        (self.width, self.height) = self.gui.get_window_dimensions();
+
+        self.wayland_client.create_surfaces(
+            &self.wayland_client_q.handle(),
+            self.width,
+            self.height,
+            outputs,
+        )?;
+        tracing::trace!("SURFACES CREATED");
+        self.publish_selection();
+        Ok(())
+    }
+
+    /// Outputs to show the switcher on, none lets the compositor choose
+    fn resolve_outputs(&self, cursor_position: Option<(i32, i32)>) -> Vec<WlOutput> {
+        let output = match &self.output_placement {
+            OutputPlacement::Auto => None,
+            OutputPlacement::All => return self.wayland_client.get_outputs(),
+            OutputPlacement::FocusedWindow => self
+                .get_active_window_id()
+                .and_then(|id| self.wayland_client.get_window_output(id)),
+            OutputPlacement::Pointer => {
+                cursor_position.and_then(|position| self.wayland_client.find_output_at(position))
+            }
+            OutputPlacement::Named(name) => {
+                let output = self.wayland_client.find_output_by_name(name);
+                if output.is_none() {
+                    tracing::warn!("There is no output named {}", name);
+                }
+                output
+            }
+        };
+
+        output.into_iter().collect()
+    }
+
+    /// Sets up the renderer for the surface, sized in buffer pixels
+    fn init_renderer_surface(&mut self) -> anyhow::Result<()> {
+        let scale = self.wayland_client.get_surface_scale();
+        let width = (self.width as f64 * scale).round() as u32;
+        let height = (self.height as f64 * scale).round() as u32;
+
+        self.renderer.init_surface(
+            &mut self.wayland_client,
+            width,
+            height,
+            self.renderer_tx.clone(),
+        )
+    }
+
     fn paint(&mut self) -> anyhow::Result<()> {
         self.pending_repaint = false;
 
@@ -12,6 +12,12 @@ struct HyprlandActiveWindow {
     size: [i32; 2],
 }
 
+#[derive(Debug, serde::Deserialize)]
+struct HyprlandCursorPosition {
+    x: i32,
+    y: i32,
+}
+
 pub struct HyprlandIpc {
     socket_path: PathBuf,
 }
@@ -46,6 +52,18 @@ impl GeometryProvider for HyprlandIpc {
 
         Ok((x, y, width, height))
     }
+
+    fn get_cursor_position(&mut self) -> Result<(i32, i32)> {
+        let json_response = self.send_command("cursorpos")?;
+
+        let position: HyprlandCursorPosition = serde_json::from_str(&json_response)?;
+
+        Ok((position.x, position.y))
+    }
+
+    fn name(&self) -> &'static str {
+        "Hyprland"
+    }
 }
 
 impl HyprlandIpc {
@@ -93,6 +111,10 @@ impl GeometryProvider for SwayIpc {
         find_focused_geometry(&tree)
             .ok_or_else(|| anyhow::anyhow!("no focused window found in sway tree"))
     }
+
+    fn name(&self) -> &'static str {
+        "Sway"
+    }
 }
 
 impl SwayIpc {
@@ -5,4 +5,9 @@ pub trait GeometryProvider {
     where
         Self: Sized;
     fn get_active_window_geometry(&mut self) -> anyhow::Result<Geometry>;
+    /// Position of the cursor in the global logical coordinate space
+    fn get_cursor_position(&mut self) -> anyhow::Result<(i32, i32)> {
+        anyhow::bail!("{} does not report the cursor position", self.name())
+    }
+    fn name(&self) -> &'static str;
 }
@@ -8,28 +8,41 @@ use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 
 enum GeometryWorkerRequestEvent<U: Copy + Send + 'static> {
     ActiveWindow(U),
+    CursorPosition,
 }
 
 #[derive(Debug)]
 pub enum GeometryWorkerEvent<U: Copy + Send + 'static> {
     ActiveWindow(U, Geometry),
+    /// Always sent in response, `None` if the position could not be determined
+    CursorPosition(Option<(i32, i32)>),
 }
 
 #[derive(Debug)]
 pub struct GeometryWorker<U: Copy + Send + 'static> {
+    provider_name: Option<&'static str>,
     request_tx: UnboundedSender<GeometryWorkerRequestEvent<U>>,
     response_rx: UnboundedReceiver<GeometryWorkerEvent<U>>,
 }
 
+impl<U: Copy + Send + 'static> Default for GeometryWorker<U> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<U: Copy + Send + 'static> GeometryWorker<U> {
-    pub fn new() -> Result<Self> {
-        let mut provider: Box<dyn GeometryProvider + Send> = if let Ok(ipc) = HyprlandIpc::new() {
-            Box::new(ipc)
-        } else if let Ok(ipc) = SwayIpc::new() {
-            Box::new(ipc)
-        } else {
-            bail!("no geometry provider");
-        };
+    /// Without a supported compositor IPC, requests are silently dropped
+    pub fn new() -> Self {
+        let mut provider: Option<Box<dyn GeometryProvider + Send>> =
+            if let Ok(ipc) = HyprlandIpc::new() {
+                Some(Box::new(ipc))
+            } else if let Ok(ipc) = SwayIpc::new() {
+                Some(Box::new(ipc))
+            } else {
+                None
+            };
+        let provider_name = provider.as_ref().map(|provider| provider.name());
 
         let (request_tx, mut request_rx) = mpsc::unbounded_channel();
         let (response_tx, response_rx) = mpsc::unbounded_channel();
@@ -38,6 +51,10 @@ impl<U: Copy + Send + 'static> GeometryWorker<U> {
             while let Some(event) = request_rx.recv().await {
                 match event {
                     GeometryWorkerRequestEvent::ActiveWindow(user_data) => {
+                        let Some(provider) = &mut provider else {
+                            continue;
+                        };
+
                         if let Ok(geometry) = provider.get_active_window_geometry()
                             && response_tx
                                 .send(GeometryWorkerEvent::ActiveWindow(user_data, geometry))
@@ -46,14 +63,30 @@ impl<U: Copy + Send + 'static> GeometryWorker<U> {
                             return;
                         }
                     }
+                    GeometryWorkerRequestEvent::CursorPosition => {
+                        let position = provider.as_mut().and_then(|provider| {
+                            provider
+                                .get_cursor_position()
+                                .inspect_err(|err| tracing::debug!("{}", err))
+                                .ok()
+                        });
+
+                        if response_tx
+                            .send(GeometryWorkerEvent::CursorPosition(position))
+                            .is_err()
+                        {
+                            return;
+                        }
+                    }
                 }
             }
         });
 
-        Ok(Self {
+        Self {
+            provider_name,
             request_tx,
             response_rx,
-        })
+        }
     }
 
     pub fn request_active_window_geometry(&mut self, user_data: U) -> Result<()> {
@@ -68,6 +101,22 @@ impl<U: Copy + Send + 'static> GeometryWorker<U> {
         Ok(())
     }
 
+    pub fn request_cursor_position(&mut self) -> Result<()> {
+        let result = self
+            .request_tx
+            .send(GeometryWorkerRequestEvent::CursorPosition);
+
+        if result.is_err() {
+            bail!("failed to send. geometry worker is down.")
+        }
+
+        Ok(())
+    }
+
+    pub fn provider_name(&self) -> Option<&'static str> {
+        self.provider_name
+    }
+
     pub async fn recv(&mut self) -> Option<GeometryWorkerEvent<U>> {
         self.response_rx.recv().await
     }
@@ -1,18 +1,25 @@
 use std::{fmt::Debug, sync::Arc};
 
 use crate::{
-    config_worker::Config, gui_state::GuiState, icon_helper::IconWorker,
+    config_worker::Config,
+    gui_state::{GuiState, Item, SelectionMove},
+    icon_helper::IconWorker,
     image_resizer::ImageResizer,
+    wayland_client_event::ToplevelState,
 };
 use egui::{
     Align, ClippedPrimitive, ColorImage, Context, CursorIcon, Event, Frame, FullOutput, Image,
-    Label, Layout, RawInput, Sense, Stroke, Style, TextureHandle, TexturesDelta, UiBuilder,
+    Label, Layout, Margin, RawInput, Sense, Stroke, Style, TextureHandle, TexturesDelta, UiBuilder,
     ahash::{HashMap, HashMapExt},
 };
 use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 
 pub enum GuiEvent {
     ItemClicked(u32),
+    /// Activate the selected window right away
+    Confirm,
+    /// Close the switcher without changing focus
+    Cancel,
 }
 
 pub struct Gui {
@@ -85,6 +92,15 @@ impl Gui {
     pub fn update_item_title(&mut self, id: u32, new_title: String) {
         self.state.update_item_title(id, new_title);
     }
+    pub fn update_item_state(&mut self, id: u32, state: ToplevelState) {
+        self.state.update_item_state(id, state);
+    }
+    pub fn update_item_parent(&mut self, id: u32, parent: Option<u32>) {
+        self.state.update_item_parent(id, parent);
+    }
+    pub fn get_focus_target(&self, id: u32) -> u32 {
+        self.state.get_focus_target(id)
+    }
     pub fn update_item_app_id(&mut self, id: u32, new_app_id: String) {
         if !self.icons.contains_key(&new_app_id) {
             self.icon_worker.get_icon(&new_app_id);
@@ -94,12 +110,18 @@ impl Gui {
     pub fn signal_item_activation(&mut self, id: u32) {
         self.state.signal_item_activation(id);
     }
+    pub fn signal_item_capture(&mut self, id: u32) {
+        self.state.signal_item_capture(id);
+    }
     pub fn remove_item(&mut self, id: u32) {
         self.state.remove_item(id);
     }
     pub fn get_first_item_id(&self) -> Option<u32> {
         self.state.get_first_item_id()
     }
+    pub fn get_items(&self) -> &[Item] {
+        self.state.get_items()
+    }
     pub fn update_item_preview(&mut self, id: u32, preview_rgba: &[u8], preview_width: u32) {
         self.state.update_item_preview(
             id,
@@ -123,27 +145,73 @@ impl Gui {
         self.state.calculate_preview_size(current_size)
     }
 
+    pub fn set_scale(&mut self, scale: f32) {
+        self.state.set_scale(scale);
+    }
+
+    pub fn get_scale(&self) -> f32 {
+        self.state.get_params().scale
+    }
+
+    pub fn select_item(&mut self, index: usize) -> bool {
+        self.state.select_item(index)
+    }
+
     pub fn select_previous_item(&mut self) {
         self.state.select_previous_item()
     }
 
+    pub fn clear_search(&mut self) {
+        self.state.set_search_query(String::new());
+    }
+
     pub fn select_next_item(&mut self) {
         self.state.select_next_item()
     }
 
     pub fn handle_events(&mut self, mut events: Vec<Event>) {
         for event in &mut events {
-            if let Event::Key {
-                key: egui::Key::Tab,
+            if let Event::Text(text) = event {
+                let query = format!("{}{}", self.state.get_search_query(), text);
+                self.state.set_search_query(query);
+                continue;
+            }
+
+            let Event::Key {
+                key,
                 pressed: true,
                 modifiers,
                 ..
             } = event
-            {
-                match modifiers.shift {
+            else {
+                continue;
+            };
+
+            match key {
+                egui::Key::Tab => match modifiers.shift {
                     true => self.state.select_previous_item(),
                     false => self.state.select_next_item(),
+                },
+                egui::Key::ArrowUp => self.state.move_selection(SelectionMove::Up),
+                egui::Key::ArrowDown => self.state.move_selection(SelectionMove::Down),
+                egui::Key::ArrowLeft => self.state.move_selection(SelectionMove::Left),
+                egui::Key::ArrowRight => self.state.move_selection(SelectionMove::Right),
+                egui::Key::PageUp => self.state.move_selection(SelectionMove::FirstRow),
+                egui::Key::PageDown => self.state.move_selection(SelectionMove::LastRow),
+                egui::Key::Home => self.state.move_selection(SelectionMove::First),
+                egui::Key::End => self.state.move_selection(SelectionMove::Last),
+                egui::Key::Enter => self.event_tx.send(GuiEvent::Confirm).unwrap(),
+                egui::Key::Backspace => {
+                    let mut query = self.state.get_search_query().to_owned();
+                    query.pop();
+                    self.state.set_search_query(query);
                 }
+                // the first escape clears the search
+                egui::Key::Escape if !self.state.get_search_query().is_empty() => {
+                    self.state.set_search_query(String::new())
+                }
+                egui::Key::Escape => self.event_tx.send(GuiEvent::Cancel).unwrap(),
+                _ => (),
             }
         }
 
@@ -164,7 +232,14 @@ impl Gui {
         self.state.set_monitor_width(width);
     }
 
-    fn build_ui(&mut self, raw_input: RawInput) -> FullOutput {
+    fn build_ui(&mut self, mut raw_input: RawInput) -> FullOutput {
+        // egui lays out in logical pixels and scales up when tessellating
+        raw_input
+            .viewports
+            .entry(raw_input.viewport_id)
+            .or_default()
+            .native_pixels_per_point = self.get_scale().into();
+
         let layout = self.state.calculate_layout();
         let mut hovered_item_updated = None;
         let item_style = Arc::new({
@@ -183,11 +258,21 @@ impl Gui {
             egui::CentralPanel::default()
                 .frame(panel_frame)
                 .show_inside(ui, |ui| {
+                    if let Some(search_rect) = layout.computed.search_rect {
+                        let mut search_ui = ui.new_child(
+                            UiBuilder::new()
+                                .max_rect(search_rect)
+                                .layout(Layout::left_to_right(Align::Center))
+                                .style(item_style.clone()),
+                        );
+                        search_ui.add(Label::new(layout.search_query).truncate());
+                    }
+
                     for (index, (rect, item)) in layout
                         .computed
                         .item_rects
                         .iter()
-                        .zip(layout.items)
+                        .zip(layout.items.iter())
                         .enumerate()
                     {
                         let mut frame_ui = ui.new_child(
@@ -229,14 +314,32 @@ impl Gui {
                                             .corner_radius(1),
                                         );
                                     }
+                                    let state = item.get_state();
+                                    for (badge, enabled) in [
+                                        ("min", state.minimized),
+                                        ("max", state.maximized),
+                                        ("full", state.fullscreen),
+                                    ] {
+                                        if enabled {
+                                            Frame::default()
+                                                .stroke(Stroke::new(
+                                                    1.0,
+                                                    layout.params.item_text_color,
+                                                ))
+                                                .inner_margin(Margin::symmetric(3, 0))
+                                                .corner_radius(3)
+                                                .show(ui, |ui| ui.small(badge));
+                                        }
+                                    }
                                     ui.add(Label::new(item.get_title()).truncate());
                                 },
                             );
                             if let Some((handle, [width, height])) = item.get_preview() {
+                                let scale = layout.params.scale;
                                 ui.add(
                                     Image::from_texture((
                                         handle.id(),
-                                        (*width as f32, *height as f32).into(),
+                                        (*width as f32 / scale, *height as f32 / scale).into(),
                                     ))
                                     .corner_radius(layout.params.preview_corner_radius),
                                 );
@@ -283,16 +386,19 @@ impl Gui {
         &self.cursor_icon
     }
 
+    /// Builds the UI for a buffer of the given size in pixels
     pub fn get_output(
         &mut self,
         width: f32,
         height: f32,
     ) -> (TexturesDelta, Vec<ClippedPrimitive>) {
+        let scale = self.get_scale();
+
         // Build egui UI with collected events
         let raw_input = egui::RawInput {
             screen_rect: Some(egui::Rect::from_min_size(
                 egui::Pos2::ZERO,
-                egui::vec2(width, height),
+                egui::vec2(width / scale, height / scale),
             )),
             focused: true,
             ..Default::default()
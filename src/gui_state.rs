@@ -1,8 +1,11 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, time::Instant};
 
 use egui::{Color32, ColorImage, Pos2, Rect, TextureHandle};
 
-use crate::config_worker::Config;
+use crate::{
+    config_worker::{Config, MinimizedWindows},
+    wayland_client_event::ToplevelState,
+};
 
 #[derive(Default)]
 pub struct Item {
@@ -10,6 +13,11 @@ pub struct Item {
     title: String,
     app_id: String,
     preview: Option<(TextureHandle, [usize; 2])>,
+    /// RGB pixels of the preview, kept around so that they can be exported
+    preview_rgb: Vec<u8>,
+    last_capture: Option<Instant>,
+    state: ToplevelState,
+    parent: Option<u32>,
 }
 
 impl Item {
@@ -24,10 +32,43 @@ impl Item {
         &self.preview
     }
 
+    pub fn get_preview_rgb(&self) -> Option<(&[u8], [usize; 2])> {
+        self.preview
+            .as_ref()
+            .map(|(_, size)| (self.preview_rgb.as_slice(), *size))
+    }
+
     pub fn get_app_id(&self) -> &str {
         &self.app_id
     }
 
+    pub fn get_raw_title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn get_last_capture(&self) -> Option<Instant> {
+        self.last_capture
+    }
+
+    pub fn get_state(&self) -> ToplevelState {
+        self.state
+    }
+
+    pub fn get_parent(&self) -> Option<u32> {
+        self.parent
+    }
+
+    /// How well the title or app id match the search query, see [`fuzzy_score`]
+    fn search_score(&self, query: &str) -> Option<usize> {
+        match (
+            fuzzy_score(query, &self.title),
+            fuzzy_score(query, &self.app_id),
+        ) {
+            (Some(title), Some(app_id)) => Some(title.min(app_id)),
+            (title, app_id) => title.or(app_id),
+        }
+    }
+
     pub fn get_title(&self) -> Cow<'_, str> {
         if self.app_id.is_empty() {
             if self.title.is_empty() {
@@ -47,6 +88,38 @@ impl Item {
     }
 }
 
+/// Narrowest the window gets while searching
+const SEARCH_MIN_WIDTH: u32 = 300;
+
+/// How well `query` matches `text`, ignoring case and whitespace in the query. Lower is better:
+/// a prefix scores 0, a contiguous match 1 and otherwise every gap between the matched
+/// characters adds to the score. `None` if the characters don't all appear in order.
+fn fuzzy_score(query: &str, text: &str) -> Option<usize> {
+    let query: String = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+    let text: String = text.chars().flat_map(char::to_lowercase).collect();
+
+    if text.starts_with(&query) {
+        return Some(0);
+    }
+    if text.contains(&query) {
+        return Some(1);
+    }
+
+    let mut text = text.chars();
+    let mut gaps = 0;
+    for (i, c) in query.chars().enumerate() {
+        let skipped = text.position(|text_char| text_char == c)?;
+        if i > 0 && skipped > 0 {
+            gaps += 1;
+        }
+    }
+    Some(1 + gaps)
+}
+
 trait ItemVecExt {
     fn with_id(&mut self, id: u32, f: impl FnOnce(&mut Item));
 }
@@ -85,6 +158,8 @@ pub struct LayoutParams {
     preview_min_width: u32,
     preview_max_width: u32,
     pub preview_corner_radius: f32,
+    /// Buffer pixels per logical pixel, previews are kept at this resolution
+    pub scale: f32,
 }
 
 impl LayoutParams {
@@ -142,6 +217,7 @@ impl Default for LayoutParams {
             preview_min_width: 100,
             preview_max_width: 200,
             preview_corner_radius: 3.0,
+            scale: 1.0,
         }
     }
 }
@@ -151,19 +227,48 @@ pub struct LayoutComputed {
     pub window_height: u32,
     pub window_width: u32,
     pub item_rects: Vec<Rect>,
+    /// Where the search query is shown while searching
+    pub search_rect: Option<Rect>,
+}
+
+/// Moves the selection through the grid of items as it is laid out
+#[derive(Debug, Clone, Copy)]
+pub enum SelectionMove {
+    /// To the closest item in the row above, wrapping around to the last row
+    Up,
+    Down,
+    /// To the previous item in the same row, wrapping around to its end
+    Left,
+    Right,
+    /// To the closest item in the first row
+    FirstRow,
+    LastRow,
+    First,
+    Last,
 }
 
 pub struct LayoutResult<'a> {
-    pub items: &'a [Item],
+    /// The items shown in the switcher, in order
+    pub items: Vec<&'a Item>,
     pub selected_item: usize,
     pub hovered_item: Option<usize>,
+    pub search_query: &'a str,
     pub params: &'a LayoutParams,
     pub computed: &'a LayoutComputed,
 }
 
 #[derive(Default)]
 pub struct GuiState {
+    /// All windows, the most recently used first
     items: Vec<Item>,
+    /// Indices into `items` of the windows shown in the switcher, in the order they are shown.
+    /// Child windows are hidden behind their parent, which takes the place of the most
+    /// recently used window of the group. The selected and hovered item refer to positions
+    /// in this list.
+    shown_items: Vec<usize>,
+    minimized_windows: MinimizedWindows,
+    /// Only windows matching this are shown
+    search_query: String,
     selected_item: usize,
     hovered_item: Option<usize>,
     needs_repaint: bool,
@@ -175,10 +280,78 @@ pub struct GuiState {
 impl GuiState {
     pub fn update_from_config(&mut self, config: &Config) {
         self.layout_params.update_from_config(config);
+        self.minimized_windows = config.minimized_windows;
+        self.update_shown_items();
+    }
+
+    fn get_index(&self, id: u32) -> Option<usize> {
+        self.items.iter().position(|item| item.id == id)
+    }
+
+    /// Follows the parents of a window up to the window that has none
+    fn get_root_index(&self, index: usize) -> usize {
+        let mut root = index;
+        // bounded in case the compositor reports a cycle
+        for _ in 0..self.items.len() {
+            match self.items[root]
+                .parent
+                .and_then(|parent| self.get_index(parent))
+            {
+                Some(parent) => root = parent,
+                None => break,
+            }
+        }
+        root
+    }
+
+    fn update_shown_items(&mut self) {
+        let mut groups: Vec<(usize, usize)> = Vec::new();
+        for index in 0..self.items.len() {
+            // a dialog matching the search shows its parent
+            let Some(score) = self.items[index].search_score(&self.search_query) else {
+                continue;
+            };
+            let root = self.get_root_index(index);
+            match groups.iter_mut().find(|(group, _)| *group == root) {
+                Some((_, best)) => *best = score.min(*best),
+                None => groups.push((root, score)),
+            }
+        }
+
+        // the best matches come first, equally good ones (and everything without a query) in
+        // the most recently used order
+        groups.sort_by_key(|(_, score)| *score);
+        let groups = groups.into_iter().map(|(root, _)| root);
+
+        let is_minimized = |index: &usize| self.items[*index].state.minimized;
+
+        self.shown_items = match self.minimized_windows {
+            MinimizedWindows::Show => groups.collect(),
+            MinimizedWindows::Last => {
+                let (minimized, others): (Vec<_>, Vec<_>) = groups.partition(is_minimized);
+                others.into_iter().chain(minimized).collect()
+            }
+            MinimizedWindows::Hide => groups.filter(|index| !is_minimized(index)).collect(),
+        };
+        self.needs_repaint = true;
+    }
+
+    /// Returns the window to focus when `id` is picked in the switcher. That is the most
+    /// recently used window of its group, so that an open modal dialog gets focused
+    /// instead of its parent.
+    pub fn get_focus_target(&self, id: u32) -> u32 {
+        let Some(index) = self.get_index(id) else {
+            return id;
+        };
+
+        (0..self.items.len())
+            .find(|other| self.get_root_index(*other) == index)
+            .map_or(id, |other| self.items[other].id)
     }
 
     pub fn add_item(&mut self, id: u32) {
         self.items.push(Item::new(id));
+        self.update_shown_items();
     }
 
     pub fn update_item_title(&mut self, id: u32, new_title: String) {
@@ -189,19 +362,34 @@ impl GuiState {
         self.items.with_id(id, |item| item.app_id = new_app_id);
         self.needs_repaint = true;
     }
+    pub fn update_item_state(&mut self, id: u32, state: ToplevelState) {
+        self.items.with_id(id, |item| item.state = state);
+        self.update_shown_items();
+    }
+    pub fn update_item_parent(&mut self, id: u32, parent: Option<u32>) {
+        self.items.with_id(id, |item| item.parent = parent);
+        self.update_shown_items();
+    }
     pub fn signal_item_activation(&mut self, id: u32) {
         if let Some(pos) = self.items.iter().position(|item| item.id == id) {
             self.items[..=pos].rotate_right(1);
-            self.needs_repaint = true;
+            self.update_shown_items();
         }
     }
+    pub fn signal_item_capture(&mut self, id: u32) {
+        self.items
+            .with_id(id, |item| item.last_capture = Instant::now().into());
+    }
     pub fn remove_item(&mut self, id: u32) {
         self.items.retain(|item| item.id != id);
-        self.needs_repaint = true;
+        self.update_shown_items();
     }
     pub fn get_first_item_id(&self) -> Option<u32> {
         self.items.first().map(|item| item.id)
     }
+    pub fn get_items(&self) -> &[Item] {
+        &self.items
+    }
     pub fn update_item_preview(
         &mut self,
         id: u32,
@@ -212,6 +400,7 @@ impl GuiState {
             let (rgb, width) = preview;
             let image_size = [width, rgb.len() / width / 3];
             let color_image = ColorImage::from_rgb(image_size, rgb);
+            item.preview_rgb = rgb.to_vec();
 
             if let Some((texture_handle, size)) = &mut item.preview {
                 texture_handle.set(color_image, Default::default());
@@ -234,33 +423,144 @@ impl GuiState {
             self.layout_params.preview_min_width,
             self.layout_params.preview_max_width,
         );
-        (preview_width, preview_height)
+
+        let scale = self.layout_params.scale;
+        (
+            (preview_width as f32 * scale).round() as u32,
+            (preview_height as f32 * scale).round() as u32,
+        )
+    }
+    pub fn set_scale(&mut self, scale: f32) {
+        if self.layout_params.scale != scale {
+            self.layout_params.scale = scale;
+            self.needs_repaint = true;
+        }
+    }
+
+    pub fn get_search_query(&self) -> &str {
+        &self.search_query
+    }
+
+    /// Filters the shown windows and orders them by how well they match, selecting the best
+    /// match
+    pub fn set_search_query(&mut self, query: String) {
+        if self.search_query == query {
+            return;
+        }
+        self.search_query = query;
+        self.update_shown_items();
+
+        // without a query the currently focused window comes first, as usual
+        match self.search_query.is_empty() {
+            true => self.reset_selected_item(),
+            false => self.selected_item = 0,
+        }
     }
 
     pub fn reset_selected_item(&mut self) {
-        self.selected_item = self.items.len().min(1);
+        self.selected_item = self.shown_items.len().min(1);
         self.needs_repaint = true;
     }
 
     pub fn get_selected_item_id(&self) -> Option<u32> {
-        self.items.get(self.selected_item).map(|item| item.id)
+        self.shown_items
+            .get(self.selected_item)
+            .map(|index| self.items[*index].id)
+    }
+    pub fn select_item(&mut self, index: usize) -> bool {
+        if index >= self.shown_items.len() {
+            return false;
+        }
+
+        self.selected_item = index;
+        self.needs_repaint = true;
+        true
     }
     pub fn select_next_item(&mut self) {
-        if self.items.len() == 0 {
+        if self.shown_items.len() == 0 {
             return;
         }
 
-        self.selected_item = (self.selected_item + 1) % self.items.len();
+        self.selected_item = (self.selected_item + 1) % self.shown_items.len();
         self.needs_repaint = true;
     }
     pub fn select_previous_item(&mut self) {
-        if self.items.len() == 0 {
+        if self.shown_items.len() == 0 {
+            return;
+        }
+
+        self.selected_item =
+            (self.selected_item + self.shown_items.len() - 1) % self.shown_items.len();
+        self.needs_repaint = true;
+    }
+    pub fn move_selection(&mut self, movement: SelectionMove) {
+        if self.shown_items.is_empty() {
             return;
         }
+        if self.layout_computed.item_rects.len() != self.shown_items.len() {
+            self.calculate_layout();
+        }
 
-        self.selected_item = (self.selected_item + self.items.len() - 1) % self.items.len();
+        let rows = self.get_rows();
+        let Some(row) = rows
+            .iter()
+            .position(|row| row.contains(&self.selected_item))
+        else {
+            self.select_item(0);
+            return;
+        };
+        let current_row = &rows[row];
+        let column = current_row
+            .iter()
+            .position(|index| *index == self.selected_item)
+            .unwrap_or_default();
+
+        self.selected_item = match movement {
+            SelectionMove::Up => {
+                self.get_closest_in_row(&rows[(row + rows.len() - 1) % rows.len()])
+            }
+            SelectionMove::Down => self.get_closest_in_row(&rows[(row + 1) % rows.len()]),
+            SelectionMove::Left => {
+                current_row[(column + current_row.len() - 1) % current_row.len()]
+            }
+            SelectionMove::Right => current_row[(column + 1) % current_row.len()],
+            SelectionMove::FirstRow => self.get_closest_in_row(&rows[0]),
+            SelectionMove::LastRow => self.get_closest_in_row(&rows[rows.len() - 1]),
+            SelectionMove::First => 0,
+            SelectionMove::Last => self.shown_items.len() - 1,
+        };
         self.needs_repaint = true;
     }
+
+    /// Indices of the shown items grouped by the row they were laid out in
+    fn get_rows(&self) -> Vec<Vec<usize>> {
+        let mut rows: Vec<Vec<usize>> = Vec::new();
+        let mut row_y = None;
+
+        for (index, rect) in self.layout_computed.item_rects.iter().enumerate() {
+            match rows.last_mut() {
+                Some(row) if row_y == Some(rect.min.y) => row.push(index),
+                _ => {
+                    rows.push(vec![index]);
+                    row_y = Some(rect.min.y);
+                }
+            }
+        }
+        rows
+    }
+
+    /// The item of `row` that is horizontally closest to the selected item
+    fn get_closest_in_row(&self, row: &[usize]) -> usize {
+        let rects = &self.layout_computed.item_rects;
+        let x = rects[self.selected_item].center().x;
+        let distance = |index: &&usize| (rects[**index].center().x - x).abs();
+
+        row.iter()
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+            .copied()
+            .unwrap_or(self.selected_item)
+    }
+
     pub fn set_hovered_item(&mut self, index: Option<usize>) {
         if self.hovered_item != index {
             self.hovered_item = index;
@@ -279,7 +579,7 @@ impl GuiState {
 
     fn get_item_width(&self, item: &Item) -> u32 {
         let content_width = match item.preview {
-            Some((_, [width, _])) => width as u32,
+            Some((_, [width, _])) => (width as f32 / self.layout_params.scale).round() as u32,
             _ => self.layout_params.preview_min_width,
         };
         content_width + self.layout_params.item_stroke * 2 + self.layout_params.item_padding * 2
@@ -312,11 +612,15 @@ impl GuiState {
 
         let available_row_width =
             self.get_window_max_width() - self.layout_params.window_padding * 2;
-        let mut longest_row_width = 0;
+        // leaves room for the query to be seen even if nothing matches
+        let mut longest_row_width = match self.search_query.is_empty() {
+            true => 0,
+            false => SEARCH_MIN_WIDTH.min(available_row_width),
+        };
 
         let mut rows: Vec<(Vec<u32>, u32)> = Vec::new();
 
-        for item in self.items.iter() {
+        for item in self.shown_items.iter().map(|index| &self.items[*index]) {
             let item_width = self.get_item_width(item);
             let needed_width = self.layout_params.items_horizontal_gap + item_width;
 
@@ -334,16 +638,32 @@ impl GuiState {
         }
 
         let row_count = rows.len() as i32;
+        let search_height = match self.search_query.is_empty() {
+            true => 0,
+            false => self.layout_params.title_height + self.layout_params.items_vertical_gap,
+        };
 
         let window_width = longest_row_width + self.layout_params.window_padding * 2;
         let window_height = row_count as u32 * self.get_item_height()
             + (row_count - 1).max(0) as u32 * self.layout_params.items_vertical_gap
-            + self.layout_params.window_padding * 2;
+            + self.layout_params.window_padding * 2
+            + search_height;
 
         let mut item_rects = Vec::new();
 
         let x = self.layout_params.window_padding as f32;
         let mut y = self.layout_params.window_padding as f32;
+
+        let search_rect = (!self.search_query.is_empty()).then(|| {
+            Rect::from_min_size(
+                Pos2 { x, y },
+                egui::vec2(
+                    longest_row_width as f32,
+                    self.layout_params.title_height as f32,
+                ),
+            )
+        });
+        y += search_height as f32;
         let row_height = self.get_item_height() as f32;
         for (row, row_width) in rows.into_iter() {
             let mut x = (longest_row_width - row_width) as f32 / 2.0 + x;
@@ -366,14 +686,55 @@ impl GuiState {
             window_height,
             window_width,
             item_rects,
+            search_rect,
         };
 
         LayoutResult {
-            items: &self.items,
+            items: self
+                .shown_items
+                .iter()
+                .map(|index| &self.items[*index])
+                .collect(),
             selected_item: self.selected_item,
             hovered_item: self.hovered_item,
+            search_query: &self.search_query,
             params: &self.layout_params,
             computed: &self.layout_computed,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_score_ranks_matches() {
+        assert_eq!(fuzzy_score("fire", "Firefox"), Some(0));
+        assert_eq!(fuzzy_score("fox", "Firefox"), Some(1));
+        assert_eq!(fuzzy_score("ffx", "Firefox"), Some(3));
+        assert_eq!(fuzzy_score("fi fox", "Firefox"), Some(2));
+        assert_eq!(fuzzy_score("xf", "Firefox"), None);
+        assert_eq!(fuzzy_score("", "Firefox"), Some(0));
+    }
+
+    #[test]
+    fn search_selects_best_match() {
+        let mut state = GuiState::default();
+        for (id, title) in [(1, "Terminal"), (2, "Mail - Thunderbird"), (3, "Thunar")] {
+            state.add_item(id);
+            state.update_item_title(id, title.into());
+        }
+
+        state.set_search_query("thun".into());
+        assert_eq!(state.get_selected_item_id(), Some(3));
+        assert_eq!(state.shown_items, [2, 1]);
+
+        // equally good matches keep the most recently used order
+        state.set_search_query("t".into());
+        assert_eq!(state.shown_items, [0, 2, 1]);
+
+        state.set_search_query(String::new());
+        assert_eq!(state.shown_items, [0, 1, 2]);
+    }
+}
@@ -0,0 +1,21 @@
+//! Bindings for `hyprland-toplevel-export-v1`, which Hyprland uses to let clients capture
+//! individual windows, including ones on other workspaces.
+
+#![allow(dead_code, non_camel_case_types, unused_unsafe, unused_variables)]
+#![allow(non_upper_case_globals, non_snake_case, unused_imports)]
+#![allow(missing_docs, clippy::all)]
+
+pub mod client {
+    use smithay_client_toolkit::reexports::client as wayland_client;
+    use smithay_client_toolkit::reexports::protocols_wlr::foreign_toplevel::v1::client::*;
+    use wayland_client::protocol::*;
+
+    pub mod __interfaces {
+        use smithay_client_toolkit::reexports::client::protocol::__interfaces::*;
+        use smithay_client_toolkit::reexports::protocols_wlr::foreign_toplevel::v1::client::__interfaces::*;
+        wayland_scanner::generate_interfaces!("./protocols/hyprland-toplevel-export-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_client_code!("./protocols/hyprland-toplevel-export-v1.xml");
+}
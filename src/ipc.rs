@@ -1,22 +1,50 @@
-use anyhow::{Context, Result, bail};
+use anyhow::{Context, Result, anyhow, bail};
 use clap::ValueEnum;
 use futures_util::{sink::SinkExt, stream::StreamExt};
-use rkyv::{Archive, Deserialize, Serialize, rancor};
-use std::{env, fs, path::PathBuf};
+use rkyv::{Archive, Deserialize, Serialize, rancor, util::AlignedVec};
+use std::{
+    env, fmt, fs, io,
+    ops::ControlFlow,
+    os::unix::fs::DirBuilderExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 use tokio::{
     net::{UnixListener, UnixStream},
-    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    sync::{
+        broadcast,
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
+};
+use tokio_util::{
+    bytes::{Bytes, BytesMut},
+    codec::{Decoder, Encoder, Framed, FramedParts, LengthDelimitedCodec, LinesCodec},
 };
-use tokio_util::codec::{Framed, LengthDelimitedCodec};
 use tracing::instrument;
 
-#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(
+    Archive, Serialize, Deserialize, serde::Serialize, serde::Deserialize, Debug, Clone, Copy,
+)]
 pub enum Direction {
     Previous,
     Next,
 }
 
-#[derive(Hash, Archive, Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq, ValueEnum)]
+#[derive(
+    Hash,
+    Archive,
+    Serialize,
+    Deserialize,
+    serde::Serialize,
+    serde::Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    ValueEnum,
+)]
 pub enum Modifier {
     Ctrl,
     Alt,
@@ -24,25 +52,456 @@ pub enum Modifier {
     Super,
 }
 
-#[derive(Archive, Serialize, Deserialize, Debug)]
+/// Which output the switcher is shown on.
+///
+/// Written as `auto`, `focused-window`, `pointer`, `all` or the name of an output.
+#[derive(
+    Archive,
+    Serialize,
+    Deserialize,
+    serde::Serialize,
+    serde::Deserialize,
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    Default,
+)]
+#[serde(from = "String", into = "String")]
+pub enum OutputPlacement {
+    /// Let the compositor decide
+    #[default]
+    Auto,
+    /// The output the active window is on
+    FocusedWindow,
+    /// The output under the pointer, which needs the compositor's IPC
+    Pointer,
+    /// Every output
+    All,
+    Named(String),
+}
+
+impl From<String> for OutputPlacement {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "auto" => Self::Auto,
+            "focused-window" => Self::FocusedWindow,
+            "pointer" => Self::Pointer,
+            "all" => Self::All,
+            _ => Self::Named(value),
+        }
+    }
+}
+
+impl From<OutputPlacement> for String {
+    fn from(value: OutputPlacement) -> Self {
+        match value {
+            OutputPlacement::Auto => "auto".into(),
+            OutputPlacement::FocusedWindow => "focused-window".into(),
+            OutputPlacement::Pointer => "pointer".into(),
+            OutputPlacement::All => "all".into(),
+            OutputPlacement::Named(name) => name,
+        }
+    }
+}
+
+impl std::str::FromStr for OutputPlacement {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.to_owned().into())
+    }
+}
+
+/// How a window is picked when activating it from the command line
+#[derive(Archive, Serialize, Deserialize, serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub enum WindowSelector {
+    Id(u32),
+    /// Shell style wildcard pattern matched against the app id
+    AppId(String),
+    /// Regular expression matched against the window title
+    Title(String),
+}
+
+#[derive(Archive, Serialize, Deserialize, serde::Serialize, serde::Deserialize, Debug)]
 pub enum IpcCommand {
     Ping,
     Show {
         direction: Option<Direction>,
         modifiers: Vec<Modifier>,
+        /// Keep the switcher open after the modifiers are released
+        #[serde(default)]
+        sticky: bool,
+        /// Overrides the output configured in the config file
+        #[serde(default)]
+        output: Option<OutputPlacement>,
     },
     Hide,
+    /// Hide the switcher and activate the selected window
+    Confirm,
+    /// Hide the switcher without activating anything
+    Cancel,
+    /// Select the window at the given position in the switcher, starting at 0
+    Select(usize),
+    List,
+    Activate(WindowSelector),
+    /// Keep the connection open and stream [`IpcEvent`]s until the client disconnects
+    Subscribe,
+    /// Hide the switcher, remove the socket and exit the daemon
+    Quit,
+    /// Re-read the configuration file
+    Reload,
+    Status,
+    /// Get the cached preview of a window
+    Preview {
+        id: u32,
+    },
 }
 
-#[derive(Archive, Serialize, Deserialize, Debug)]
+#[derive(Archive, Serialize, Deserialize, serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub title: String,
+    pub app_id: String,
+    pub has_preview: bool,
+    /// The window this one is a dialog of
+    pub parent: Option<u32>,
+}
+
+/// A window preview as packed 8-bit RGB pixels, row by row
+#[derive(Archive, Serialize, Deserialize, serde::Serialize, serde::Deserialize, Clone)]
+pub struct PreviewImage {
+    pub width: u32,
+    pub height: u32,
+    pub rgb: Vec<u8>,
+}
+
+impl fmt::Debug for PreviewImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PreviewImage")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Archive, Serialize, Deserialize, serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct OutputStatus {
+    pub name: Option<String>,
+    pub scale_factor: i32,
+    pub logical_position: Option<(i32, i32)>,
+    pub logical_size: Option<(i32, i32)>,
+    /// Size of the current mode in physical pixels
+    pub mode_size: Option<(i32, i32)>,
+}
+
+#[derive(Archive, Serialize, Deserialize, serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct WindowStatus {
+    pub id: u32,
+    pub app_id: String,
+    pub preview_size: Option<(u32, u32)>,
+    /// Milliseconds since the last successful screencopy of this window
+    pub last_capture_ms: Option<u64>,
+}
+
+#[derive(Archive, Serialize, Deserialize, serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct DaemonStatus {
+    pub version: String,
+    pub geometry_provider: Option<String>,
+    pub renderer: String,
+    /// Interface name and version of the bound wayland globals
+    pub protocols: Vec<(String, u32)>,
+    pub outputs: Vec<OutputStatus>,
+    pub windows: Vec<WindowStatus>,
+    /// Memory used by the cached preview textures in bytes
+    pub preview_bytes: u64,
+    /// Size of the shared memory pool used for the surface and captures in bytes
+    pub shm_pool_bytes: u64,
+}
+
+#[derive(Archive, Serialize, Deserialize, serde::Serialize, serde::Deserialize, Debug)]
 pub enum IpcCommandResponse {
     Success,
     Error(String),
+    /// Windows tracked by the daemon, most recently used first
+    WindowList(Vec<WindowInfo>),
+    Event(IpcEvent),
+    Status(DaemonStatus),
+    Preview(PreviewImage),
+}
+
+#[derive(Archive, Serialize, Deserialize, serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub enum IpcEvent {
+    WindowAdded { id: u32 },
+    WindowRemoved { id: u32 },
+    WindowActivated { window: WindowInfo },
+    WindowTitleChanged { id: u32, title: String },
+    SwitcherShown,
+    SwitcherHidden,
+    SelectionChanged { id: u32 },
+}
+
+#[derive(Debug)]
+pub struct IpcResponder {
+    response_tx: oneshot::Sender<IpcCommandResponse>,
+    sent_rx: oneshot::Receiver<()>,
 }
 
-pub struct AlttabwayIpc {}
+impl IpcResponder {
+    pub fn respond(self, response: IpcCommandResponse) {
+        // the client may have disconnected already, which is fine
+        let _ = self.response_tx.send(response);
+    }
+
+    /// Responds and waits until the response has been written to the client
+    pub async fn respond_and_wait(self, response: IpcCommandResponse) {
+        if self.response_tx.send(response).is_ok() {
+            let _ = self.sent_rx.await;
+        }
+    }
+}
+
+/// A command received over the socket, along with a way to answer it
+#[derive(Debug)]
+pub struct IpcRequest {
+    pub command: IpcCommand,
+    pub responder: IpcResponder,
+}
+
+pub struct IpcServer {
+    socket_path: PathBuf,
+    request_rx: UnboundedReceiver<IpcRequest>,
+    event_tx: broadcast::Sender<IpcEvent>,
+}
+
+impl IpcServer {
+    /// Number of events buffered for each subscriber before it starts missing events
+    const EVENT_CAPACITY: usize = 64;
+
+    pub async fn recv(&mut self) -> Option<IpcRequest> {
+        self.request_rx.recv().await
+    }
+
+    pub fn publish(&self, event: IpcEvent) {
+        // fails only if there are no subscribers
+        let _ = self.event_tx.send(event);
+    }
+
+    /// Removes the socket file so that no new clients can connect
+    pub fn remove_socket(&self) {
+        if let Err(err) = fs::remove_file(&self.socket_path) {
+            tracing::warn!("Failed to remove socket {:?}: {}", self.socket_path, err);
+        }
+    }
+}
+
+/// rkyv needs its input to be aligned, which frames split off a read buffer may not be
+fn aligned(bytes: &[u8]) -> AlignedVec {
+    let mut aligned = AlignedVec::new();
+    aligned.extend_from_slice(bytes);
+    aligned
+}
+
+/// Version of the rkyv wire format. Bump this whenever [`IpcCommand`], [`IpcCommandResponse`]
+/// or any type they contain changes shape.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// First frame exchanged on a rkyv connection. Unlike commands it is encoded by hand so that
+/// clients and daemons built from different versions can always read each other's hello.
+#[derive(Debug)]
+struct Hello {
+    protocol: u32,
+    version: String,
+}
+
+impl Hello {
+    const MAGIC: &[u8] = b"alttabway-hello\0";
+
+    fn current() -> Self {
+        Self {
+            protocol: PROTOCOL_VERSION,
+            version: env!("CARGO_PKG_VERSION").into(),
+        }
+    }
+
+    fn encode(&self) -> Bytes {
+        let mut bytes = Self::MAGIC.to_vec();
+        bytes.extend_from_slice(&self.protocol.to_le_bytes());
+        bytes.extend_from_slice(self.version.as_bytes());
+        bytes.into()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let rest = bytes.strip_prefix(Self::MAGIC)?;
+        let (protocol, version) = rest.split_first_chunk::<4>()?;
+
+        Some(Self {
+            protocol: u32::from_le_bytes(*protocol),
+            version: String::from_utf8_lossy(version).into(),
+        })
+    }
+}
+
+/// The running daemon speaks a different version of the rkyv protocol than this binary
+#[derive(Debug)]
+pub struct ProtocolMismatch {
+    /// `None` if the daemon predates the version handshake
+    pub daemon_version: Option<String>,
+}
+
+impl fmt::Display for ProtocolMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.daemon_version {
+            Some(daemon_version) => write!(
+                f,
+                "The running alttabway daemon (version {}) does not match this binary (version {}).",
+                daemon_version,
+                env!("CARGO_PKG_VERSION")
+            )?,
+            None => write!(
+                f,
+                "The running alttabway daemon is older than this binary (version {}).",
+                env!("CARGO_PKG_VERSION")
+            )?,
+        }
+        write!(f, " Restart it with `alttabway daemon --replace`.")
+    }
+}
+
+impl std::error::Error for ProtocolMismatch {}
+
+/// The daemon accepts two wire formats on the same socket: length-delimited rkyv, as used
+/// by the alttabway CLI, and newline-delimited JSON for scripts. The format is picked from
+/// the first byte a client sends. A rkyv frame starts with its big-endian length, which is
+/// never `{` or `"` for any reasonably sized command.
+#[derive(Debug, Clone, Copy)]
+enum WireFormat {
+    Rkyv,
+    Json,
+}
+
+impl WireFormat {
+    fn detect(first_byte: u8) -> Self {
+        match first_byte {
+            b'{' | b'"' | b' ' | b'\t' | b'\r' | b'\n' => Self::Json,
+            _ => Self::Rkyv,
+        }
+    }
+
+    fn codec(self) -> IpcCodec {
+        match self {
+            Self::Rkyv => IpcCodec::Rkyv(LengthDelimitedCodec::new()),
+            Self::Json => IpcCodec::Json(LinesCodec::new_with_max_length(Self::MAX_JSON_LINE)),
+        }
+    }
+
+    const MAX_JSON_LINE: usize = 64 * 1024;
+
+    fn decode_command(self, bytes: &[u8]) -> Result<IpcCommand> {
+        Ok(match self {
+            Self::Rkyv => rkyv::from_bytes::<IpcCommand, rancor::Error>(&aligned(bytes))?,
+            Self::Json => serde_json::from_slice(bytes)?,
+        })
+    }
+
+    fn encode_response(self, response: &IpcCommandResponse) -> Result<Bytes> {
+        Ok(match self {
+            Self::Rkyv => rkyv::to_bytes::<rancor::Error>(response)?.into_vec().into(),
+            Self::Json => serde_json::to_vec(response)?.into(),
+        })
+    }
+}
+
+/// Splits the byte stream into frames according to the [`WireFormat`] in use
+enum IpcCodec {
+    Rkyv(LengthDelimitedCodec),
+    Json(LinesCodec),
+}
+
+impl Decoder for IpcCodec {
+    type Item = BytesMut;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        match self {
+            IpcCodec::Rkyv(codec) => codec.decode(src),
+            IpcCodec::Json(codec) => Ok(codec
+                .decode(src)
+                .map_err(io::Error::other)?
+                .map(|line| line.as_bytes().into())),
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        match self {
+            IpcCodec::Rkyv(codec) => codec.decode_eof(src),
+            IpcCodec::Json(codec) => Ok(codec
+                .decode_eof(src)
+                .map_err(io::Error::other)?
+                .map(|line| line.as_bytes().into())),
+        }
+    }
+}
+
+impl Encoder<Bytes> for IpcCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> io::Result<()> {
+        match self {
+            IpcCodec::Rkyv(codec) => codec.encode(item, dst),
+            IpcCodec::Json(_) => {
+                dst.extend_from_slice(&item);
+                dst.extend_from_slice(b"\n");
+                Ok(())
+            }
+        }
+    }
+}
+
+pub struct AlttabwayIpc {
+    socket_path: PathBuf,
+    /// Where daemons listened before the socket was named after the Wayland display
+    legacy_socket_path: Option<PathBuf>,
+}
 
 impl AlttabwayIpc {
+    /// Uses `socket_path` if given, otherwise a socket specific to the current Wayland display
+    pub fn new(socket_path: Option<PathBuf>) -> Result<Self> {
+        let (socket_path, legacy_socket_path) = match socket_path {
+            Some(socket_path) => (socket_path, None),
+            None => (Self::get_socket_path()?, Self::get_legacy_socket_path()),
+        };
+
+        Ok(Self {
+            socket_path,
+            legacy_socket_path,
+        })
+    }
+
+    fn get_legacy_socket_path() -> Option<PathBuf> {
+        let xdg_runtime_dir = env::var("XDG_RUNTIME_DIR").ok()?;
+        Some(PathBuf::from(format!(
+            "{}/alttabway/.socket.sock",
+            xdg_runtime_dir
+        )))
+    }
+
+    /// Explains why no daemon answered on our socket if one from before the socket was
+    /// renamed is still running, so that it's reported as a version mismatch
+    async fn legacy_daemon_error(&self, err: anyhow::Error) -> anyhow::Error {
+        if err.is::<ProtocolMismatch>() {
+            return err;
+        }
+        let Some(legacy_socket_path) = &self.legacy_socket_path else {
+            return err;
+        };
+
+        match Self::connect(legacy_socket_path).await {
+            Err(legacy_err) if legacy_err.is::<ProtocolMismatch>() => legacy_err,
+            _ => err,
+        }
+    }
+
     #[instrument]
     fn get_socket_path() -> Result<PathBuf> {
         let xdg_runtime_dir = env::var("XDG_RUNTIME_DIR")?;
@@ -50,50 +509,203 @@ impl AlttabwayIpc {
         let mut socket_dir_path = PathBuf::from(format!("{}/alttabway", xdg_runtime_dir));
 
         // create directory if it does not exist
-        let _ = fs::create_dir(&socket_dir_path);
+        let _ = fs::DirBuilder::new().mode(0o700).create(&socket_dir_path);
+
+        // WAYLAND_DISPLAY may also be an absolute path to the compositor socket. libwayland
+        // falls back to wayland-0 when it is unset, so the daemon ends up on that display too.
+        let display = env::var_os("WAYLAND_DISPLAY").unwrap_or_else(|| "wayland-0".into());
+        let display = Path::new(&display)
+            .file_name()
+            .context("WAYLAND_DISPLAY is not a valid display name")?;
 
-        socket_dir_path.push(".socket.sock");
+        socket_dir_path.push(format!("{}.sock", display.to_string_lossy()));
         Ok(socket_dir_path)
     }
 
-    async fn handle_connection(stream: UnixStream, tx: UnboundedSender<IpcCommand>) -> Result<()> {
-        let mut framed = Framed::new(stream, LengthDelimitedCodec::new());
+    async fn handle_connection(
+        stream: UnixStream,
+        tx: UnboundedSender<IpcRequest>,
+        event_tx: broadcast::Sender<IpcEvent>,
+    ) -> Result<()> {
+        let mut first_byte = [0u8; 1];
+        loop {
+            stream.readable().await?;
+            match stream.try_read(&mut first_byte) {
+                Ok(0) => return Ok(()),
+                Ok(_) => break,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let format = WireFormat::detect(first_byte[0]);
+        let mut parts = FramedParts::new::<Bytes>(stream, format.codec());
+        parts.read_buf.extend_from_slice(&first_byte);
+        let mut framed = Framed::from_parts(parts);
 
         while let Some(result) = framed.next().await {
             let Ok(bytes) = result else { continue };
 
-            let response =
-                if let Ok(command) = rkyv::from_bytes::<IpcCommand, rancor::Error>(&bytes) {
+            if let WireFormat::Rkyv = format
+                && let Some(hello) = Hello::decode(&bytes)
+            {
+                if hello.protocol != PROTOCOL_VERSION {
+                    tracing::warn!(
+                        "client version {} uses IPC protocol {}, expected {}",
+                        hello.version,
+                        hello.protocol,
+                        PROTOCOL_VERSION
+                    );
+                }
+                framed.send(Hello::current().encode()).await?;
+                continue;
+            }
+
+            let (response, sent_tx) = match format.decode_command(&bytes) {
+                Ok(IpcCommand::Subscribe) => {
+                    return Self::stream_events(framed, format, event_tx.subscribe()).await;
+                }
+                Ok(command) => {
                     tracing::trace!("IPC RECEIVED");
-                    tx.send(command)?;
-                    IpcCommandResponse::Success
-                } else {
-                    IpcCommandResponse::Error(
-                        "Unrecognized IPC command. Try reloading the alttabway daemon?".into(),
-                    )
-                };
+                    let (response_tx, response_rx) = oneshot::channel();
+                    let (sent_tx, sent_rx) = oneshot::channel();
+                    tx.send(IpcRequest {
+                        command,
+                        responder: IpcResponder {
+                            response_tx,
+                            sent_rx,
+                        },
+                    })?;
+
+                    (response_rx.await?, Some(sent_tx))
+                }
+                Err(err) => {
+                    let message = match format {
+                        WireFormat::Rkyv => {
+                            "Unrecognized IPC command. Try reloading the alttabway daemon?".into()
+                        }
+                        WireFormat::Json => format!("Unrecognized IPC command: {}", err),
+                    };
+                    (IpcCommandResponse::Error(message), None)
+                }
+            };
 
-            let response_bytes = rkyv::to_bytes::<rancor::Error>(&response)?;
-            framed.send(response_bytes.into_vec().into()).await?;
+            framed.send(format.encode_response(&response)?).await?;
+
+            if let Some(sent_tx) = sent_tx {
+                let _ = sent_tx.send(());
+            }
         }
         Ok(())
     }
 
-    async fn listen(listener: UnixListener, tx: UnboundedSender<IpcCommand>) -> Result<()> {
+    async fn stream_events(
+        mut framed: Framed<UnixStream, IpcCodec>,
+        format: WireFormat,
+        mut event_rx: broadcast::Receiver<IpcEvent>,
+    ) -> Result<()> {
+        framed
+            .send(format.encode_response(&IpcCommandResponse::Success)?)
+            .await?;
+
+        loop {
+            tokio::select! {
+                result = event_rx.recv() => {
+                    let event = match result {
+                        Ok(event) => event,
+                        Err(broadcast::error::RecvError::Lagged(count)) => {
+                            tracing::warn!("IPC subscriber is too slow, dropped {} events", count);
+                            continue;
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                    };
+
+                    framed.send(format.encode_response(&IpcCommandResponse::Event(event))?).await?;
+                }
+                // subscribers are not expected to send anything, stop once they hang up
+                frame = framed.next() => {
+                    if frame.is_none() {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    async fn listen(
+        listener: UnixListener,
+        tx: UnboundedSender<IpcRequest>,
+        event_tx: broadcast::Sender<IpcEvent>,
+    ) -> Result<()> {
+        let uid = unsafe { libc::getuid() };
+
         loop {
             let (stream, _) = listener.accept().await?;
-            let tx_copy = tx.clone();
-            tokio::spawn(Self::handle_connection(stream, tx_copy));
+
+            // only the user running the daemon may control it
+            match stream.peer_cred() {
+                Ok(cred) if cred.uid() == uid => (),
+                Ok(cred) => {
+                    tracing::warn!(
+                        "Rejecting IPC connection from uid {} (pid {:?})",
+                        cred.uid(),
+                        cred.pid()
+                    );
+                    continue;
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        "Rejecting IPC connection, could not get credentials: {}",
+                        err
+                    );
+                    continue;
+                }
+            }
+
+            tokio::spawn(Self::handle_connection(
+                stream,
+                tx.clone(),
+                event_tx.clone(),
+            ));
+        }
+    }
+
+    /// Connects to the daemon and checks that it speaks the same protocol version
+    async fn connect(socket_path: &PathBuf) -> Result<Framed<UnixStream, LengthDelimitedCodec>> {
+        let stream = UnixStream::connect(&socket_path).await?;
+
+        let mut framed = Framed::new(stream, LengthDelimitedCodec::new());
+
+        framed.send(Hello::current().encode()).await?;
+
+        let bytes = framed
+            .next()
+            .await
+            .context("stream closed without response?")??;
+
+        // daemons that predate the handshake answer with an error instead
+        let Some(hello) = Hello::decode(&bytes) else {
+            return Err(ProtocolMismatch {
+                daemon_version: None,
+            }
+            .into());
+        };
+
+        if hello.protocol != PROTOCOL_VERSION {
+            return Err(ProtocolMismatch {
+                daemon_version: hello.version.into(),
+            }
+            .into());
         }
+
+        Ok(framed)
     }
 
     async fn send_socket_command(
         socket_path: &PathBuf,
         command: IpcCommand,
     ) -> Result<IpcCommandResponse> {
-        let stream = UnixStream::connect(&socket_path).await?;
-
-        let mut framed = Framed::new(stream, LengthDelimitedCodec::new());
+        let mut framed = Self::connect(socket_path).await?;
 
         framed
             .send(rkyv::to_bytes::<rancor::Error>(&command)?.into_vec().into())
@@ -104,33 +716,135 @@ impl AlttabwayIpc {
             .await
             .context("stream closed without response?")??;
 
-        let response = rkyv::from_bytes::<IpcCommandResponse, rancor::Error>(&bytes)?;
+        let response = rkyv::from_bytes::<IpcCommandResponse, rancor::Error>(&aligned(&bytes))?;
         Ok(response)
     }
 
-    #[instrument]
-    pub async fn start_server() -> Result<UnboundedReceiver<IpcCommand>> {
-        let socket_path = Self::get_socket_path()?;
+    /// Sends SIGTERM to the daemon listening on the socket and waits for it to go away
+    async fn terminate_daemon(socket_path: &PathBuf) -> Result<()> {
+        let stream = UnixStream::connect(&socket_path).await?;
+        let pid = stream
+            .peer_cred()?
+            .pid()
+            .context("could not determine the pid of the running daemon")?;
+        drop(stream);
+
+        // make sure we don't kill an unrelated process that happens to own the socket
+        let process_name = fs::read_to_string(format!("/proc/{}/comm", pid))?;
+        if process_name.trim() != env!("CARGO_PKG_NAME") {
+            bail!(
+                "socket is owned by {} ({}), refusing to kill it",
+                process_name.trim(),
+                pid
+            );
+        }
+
+        tracing::info!("Stopping the running daemon ({})", pid);
+        if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        for _ in 0..50 {
+            if UnixStream::connect(&socket_path).await.is_err() {
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        Err(anyhow!("daemon ({}) did not exit in time", pid))
+    }
+
+    /// Starts listening for commands. If another daemon is already running, this fails unless
+    /// `replace` is set, in which case the other daemon is stopped first.
+    #[instrument(skip(self))]
+    pub async fn start_server(&self, replace: bool) -> Result<IpcServer> {
+        let socket_path = self.socket_path.clone();
         tracing::info!("path {:?}", socket_path);
 
-        if let Ok(_) = Self::send_socket_command(&socket_path, IpcCommand::Ping).await {
-            bail!("Another instance is already running.");
+        match Self::send_socket_command(&socket_path, IpcCommand::Ping).await {
+            Ok(_) if replace => {
+                tracing::info!("Asking the running daemon to quit");
+                Self::send_socket_command(&socket_path, IpcCommand::Quit).await?;
+            }
+            // the running daemon may not understand our commands, so stop it the hard way
+            Err(_) if replace && UnixStream::connect(&socket_path).await.is_ok() => {
+                Self::terminate_daemon(&socket_path).await?
+            }
+            Ok(_) => bail!("Another instance is already running."),
+            Err(err) if err.is::<ProtocolMismatch>() => bail!(
+                "Another instance of a different version is already running. \
+                 Use `alttabway daemon --replace` to replace it."
+            ),
+            Err(_) => (),
+        }
+
+        if let Some(legacy_socket_path) = &self.legacy_socket_path
+            && UnixStream::connect(legacy_socket_path).await.is_ok()
+        {
+            if !replace {
+                bail!(
+                    "An older instance is still running. \
+                     Use `alttabway daemon --replace` to replace it."
+                );
+            }
+            Self::terminate_daemon(legacy_socket_path).await?;
+            let _ = fs::remove_file(legacy_socket_path);
         }
 
         let _ = fs::remove_file(&socket_path);
 
         let listener = UnixListener::bind(&socket_path)?;
 
-        let (tx, rx) = mpsc::unbounded_channel();
+        let (request_tx, request_rx) = mpsc::unbounded_channel();
+        let (event_tx, _) = broadcast::channel(IpcServer::EVENT_CAPACITY);
+
+        tokio::spawn(Self::listen(listener, request_tx, event_tx.clone()));
 
-        tokio::spawn(Self::listen(listener, tx));
+        Ok(IpcServer {
+            socket_path,
+            request_rx,
+            event_tx,
+        })
+    }
 
-        Ok(rx)
+    pub async fn send_command(&self, command: IpcCommand) -> Result<IpcCommandResponse> {
+        match Self::send_socket_command(&self.socket_path, command).await {
+            Ok(response) => Ok(response),
+            Err(err) => Err(self.legacy_daemon_error(err).await),
+        }
     }
 
-    pub async fn send_command(command: IpcCommand) -> Result<IpcCommandResponse> {
-        let socket_path = Self::get_socket_path()?;
+    /// Streams events to `on_event` until the daemon closes the connection or `on_event` breaks
+    pub async fn subscribe(
+        &self,
+        mut on_event: impl FnMut(IpcEvent) -> ControlFlow<()>,
+    ) -> Result<()> {
+        let mut framed = match Self::connect(&self.socket_path).await {
+            Ok(framed) => framed,
+            Err(err) => return Err(self.legacy_daemon_error(err).await),
+        };
 
-        Self::send_socket_command(&socket_path, command).await
+        framed
+            .send(
+                rkyv::to_bytes::<rancor::Error>(&IpcCommand::Subscribe)?
+                    .into_vec()
+                    .into(),
+            )
+            .await?;
+
+        while let Some(bytes) = framed.next().await {
+            match rkyv::from_bytes::<IpcCommandResponse, rancor::Error>(&aligned(&bytes?))? {
+                IpcCommandResponse::Event(event) => {
+                    if on_event(event).is_break() {
+                        break;
+                    }
+                }
+                IpcCommandResponse::Success => (),
+                IpcCommandResponse::Error(err) => bail!(err),
+                response => bail!("unexpected response {:?}", response),
+            }
+        }
+
+        Ok(())
     }
 }
@@ -5,10 +5,15 @@ pub mod geometry_provider;
 pub mod geometry_worker;
 pub mod gui;
 pub mod gui_state;
+pub mod hyprland_toplevel_export;
 pub mod icon_helper;
 pub mod image_resizer;
 pub mod ipc;
+pub mod pixel_format;
 pub mod renderer;
+pub mod shm_pool;
+pub mod stitch;
 pub mod timer;
 pub mod wayland_client;
 pub mod wayland_client_event;
+pub mod window_matcher;
@@ -1,13 +1,29 @@
 use alttabway::{
     daemon::Daemon,
-    ipc::{AlttabwayIpc, Direction, IpcCommand, Modifier},
+    ipc::{
+        AlttabwayIpc, DaemonStatus, Direction, IpcCommand, IpcCommandResponse, Modifier,
+        OutputPlacement, PreviewImage, ProtocolMismatch, WindowSelector,
+    },
 };
+use anyhow::Context;
 use clap::{ArgGroup, Parser, Subcommand};
+use image::{ImageFormat, RgbImage, imageops, imageops::FilterType};
+use std::{
+    io::{self, Cursor, Write},
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 #[command(propagate_version = true)]
 struct Cli {
+    /// Path of the daemon's control socket. Defaults to a socket for the current
+    /// WAYLAND_DISPLAY in $XDG_RUNTIME_DIR/alttabway
+    #[arg(long, global = true)]
+    socket: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -15,7 +31,11 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Start the alttabway daemon
-    Daemon,
+    Daemon {
+        /// Stop an already running daemon, e.g. one left over from before an upgrade
+        #[arg(long)]
+        replace: bool,
+    },
 
     /// Show the alt-tab window (requires daemon to be running)
     #[command(group(ArgGroup::new("direction").args(["next", "previous"])))]
@@ -31,20 +51,247 @@ enum Commands {
         /// Modifier keys that need to be held for the window to be shown
         #[arg(long, value_enum, default_values_t = Daemon::DEFAULT_REQ_MODIFIER, value_delimiter = ',')]
         modifiers_held: Vec<Modifier>,
+
+        /// Keep the window open after the modifiers are released, until it is confirmed,
+        /// cancelled or a window is clicked
+        #[arg(long)]
+        sticky: bool,
+
+        /// Output to show the window on: auto, focused-window, pointer, all or an output name.
+        /// Defaults to the `output` set in the config file
+        #[arg(long)]
+        output: Option<OutputPlacement>,
+    },
+
+    /// Hide the alt-tab window and focus the selected window
+    Confirm,
+
+    /// Hide the alt-tab window without changing focus
+    Cancel,
+
+    /// Select a window in the alt-tab window by its position, starting at 0
+    Select { index: usize },
+
+    /// List the windows tracked by the daemon, most recently used first
+    ///
+    /// Each line contains the window id, app id, whether a preview exists and the title,
+    /// separated by tabs.
+    List,
+
+    /// Focus a window tracked by the daemon, exits with an error if none matched
+    ///
+    /// Windows are searched in most recently used order. If the focused window matches, the
+    /// next matching window is activated instead, so repeated calls cycle through all matches.
+    #[command(group(ArgGroup::new("selector").required(true).args(["id", "app_id", "title"])))]
+    Activate {
+        /// Window id, as shown by `alttabway list`
+        #[arg(long)]
+        id: Option<u32>,
+
+        /// Shell style wildcard pattern matched against the app id, e.g. `*firefox*`
+        #[arg(long)]
+        app_id: Option<String>,
+
+        /// Regular expression matched against the window title, prefix with `(?i)` to ignore case
+        #[arg(long)]
+        title: Option<String>,
+    },
+
+    /// Print daemon events as JSON, one per line, until the daemon exits
+    Subscribe,
+
+    /// Write the preview of a window as a PNG image
+    Preview {
+        /// Window id, as shown by `alttabway list`
+        #[arg(long)]
+        id: u32,
+
+        /// File to write the image to, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Scale the preview to this width. Keeps the aspect ratio unless --height is given too
+        #[arg(long)]
+        width: Option<u32>,
+
+        /// Scale the preview to this height. Keeps the aspect ratio unless --width is given too
+        #[arg(long)]
+        height: Option<u32>,
+    },
+
+    /// Stop the daemon
+    Quit,
+
+    /// Make the daemon re-read its configuration file
+    Reload,
+
+    /// Show diagnostics about the running daemon
+    Status {
+        /// Print the status as JSON
+        #[arg(long)]
+        json: bool,
     },
 }
 
+fn print_status(status: &DaemonStatus) {
+    println!("alttabway daemon {}", status.version);
+    println!(
+        "Geometry provider: {}",
+        status.geometry_provider.as_deref().unwrap_or("none")
+    );
+    println!("Renderer: {}", status.renderer);
+
+    println!("Protocols:");
+    for (interface, version) in &status.protocols {
+        println!("  {} v{}", interface, version);
+    }
+
+    println!("Outputs:");
+    for output in &status.outputs {
+        let (x, y) = output.logical_position.unwrap_or_default();
+        let (width, height) = output.logical_size.unwrap_or_default();
+        let (mode_width, mode_height) = output.mode_size.unwrap_or_default();
+        println!(
+            "  {}: {}x{} at {},{} (mode {}x{}, scale {})",
+            output.name.as_deref().unwrap_or("unnamed"),
+            width,
+            height,
+            x,
+            y,
+            mode_width,
+            mode_height,
+            output.scale_factor
+        );
+    }
+
+    let preview_count = status
+        .windows
+        .iter()
+        .filter(|window| window.preview_size.is_some())
+        .count();
+    println!(
+        "Windows: {} tracked, {} previews cached ({} KiB)",
+        status.windows.len(),
+        preview_count,
+        status.preview_bytes / 1024
+    );
+    println!("Shared memory pool: {} KiB", status.shm_pool_bytes / 1024);
+    for window in &status.windows {
+        let preview = match window.preview_size {
+            Some((width, height)) => format!("preview {}x{}", width, height),
+            None => "no preview".into(),
+        };
+        let capture = match window.last_capture_ms {
+            Some(ms) => format!("captured {:.1}s ago", ms as f64 / 1000.0),
+            None => "never captured".into(),
+        };
+        println!(
+            "  {} {}: {}, {}",
+            window.id, window.app_id, preview, capture
+        );
+    }
+}
+
+/// Largest width or height a preview is resized to
+const MAX_PREVIEW_SIZE: u32 = 8192;
+
+fn write_preview(
+    preview: PreviewImage,
+    size: (Option<u32>, Option<u32>),
+    output: Option<&Path>,
+) -> anyhow::Result<()> {
+    let image = RgbImage::from_raw(preview.width, preview.height, preview.rgb)
+        .context("preview has an invalid size")?;
+
+    let image = match size {
+        (None, None) => image,
+        (width, height) => {
+            // a missing dimension keeps the aspect ratio, computed in u64 to avoid overflowing
+            let scale = |length: u32, numerator: u32, denominator: u32| {
+                let scaled = length as u64 * numerator as u64 / denominator.max(1) as u64;
+                scaled.clamp(1, MAX_PREVIEW_SIZE as u64) as u32
+            };
+            let width = width.unwrap_or_else(|| {
+                scale(height.unwrap_or_default(), image.width(), image.height())
+            });
+            let height = height.unwrap_or_else(|| scale(width, image.height(), image.width()));
+            imageops::resize(
+                &image,
+                width.clamp(1, MAX_PREVIEW_SIZE),
+                height.clamp(1, MAX_PREVIEW_SIZE),
+                FilterType::Triangle,
+            )
+        }
+    };
+
+    match output {
+        Some(path) => image.save_with_format(path, ImageFormat::Png)?,
+        None => {
+            // the png encoder needs to seek, which stdout can't do
+            let mut png = Cursor::new(Vec::new());
+            image.write_to(&mut png, ImageFormat::Png)?;
+            io::stdout().write_all(png.get_ref())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn report_ipc_error(err: anyhow::Error) {
+    match err.downcast_ref::<ProtocolMismatch>() {
+        Some(mismatch) => tracing::error!("{}", mismatch),
+        None => tracing::warn!(
+            "Please check if the alttabway daemon is running. Error: {}",
+            err
+        ),
+    }
+}
+
+/// Exit code for commands that don't return any data
+fn exit_code(response: Option<IpcCommandResponse>) -> ExitCode {
+    match response {
+        Some(IpcCommandResponse::Success) => ExitCode::SUCCESS,
+        Some(IpcCommandResponse::Error(err)) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+        Some(response) => {
+            tracing::warn!("Unexpected response: {:?}", response);
+            ExitCode::FAILURE
+        }
+        None => ExitCode::FAILURE,
+    }
+}
+
+async fn send_command(ipc: &AlttabwayIpc, command: IpcCommand) -> Option<IpcCommandResponse> {
+    match ipc.send_command(command).await {
+        Ok(response) => response.into(),
+        Err(err) => {
+            report_ipc_error(err);
+            None
+        }
+    }
+}
+
 #[tokio::main(flavor = "multi_thread")]
-async fn main() {
+async fn main() -> ExitCode {
     tracing_subscriber::fmt::init();
     let cli = Cli::parse();
 
+    let ipc = match AlttabwayIpc::new(cli.socket) {
+        Ok(ipc) => ipc,
+        Err(err) => {
+            tracing::error!("Could not determine the socket path: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
     // You can check for the existence of subcommands, and if found use their
     // matches just as you would the top level cmd
     match &cli.command {
-        Commands::Daemon => {
+        Commands::Daemon { replace } => {
             tracing::debug!("requesting daemon start");
-            if let Err(err) = Daemon::start().await {
+            if let Err(err) = Daemon::start(&ipc, *replace).await {
                 tracing::info!("Exiting: {}", err);
             }
         }
@@ -52,6 +299,8 @@ async fn main() {
             next,
             previous,
             modifiers_held,
+            sticky,
+            output,
         } => {
             let direction = if *next {
                 Some(Direction::Next)
@@ -63,18 +312,104 @@ async fn main() {
 
             tracing::debug!("Modifiers required to be held: {:?}", modifiers_held);
 
-            match AlttabwayIpc::send_command(IpcCommand::Show {
-                direction,
-                modifiers: modifiers_held.clone(),
-            })
+            if let Some(response) = send_command(
+                &ipc,
+                IpcCommand::Show {
+                    direction,
+                    modifiers: modifiers_held.clone(),
+                    sticky: *sticky,
+                    output: output.clone(),
+                },
+            )
             .await
             {
-                Ok(response) => tracing::info!("{:?}", response),
-                Err(err) => tracing::warn!(
-                    "Please check if the alttabway daemon is running. Error: {}",
-                    err
-                ),
+                tracing::info!("{:?}", response);
             }
         }
+        Commands::List => match send_command(&ipc, IpcCommand::List).await {
+            Some(IpcCommandResponse::WindowList(windows)) => {
+                for window in windows {
+                    println!(
+                        "{}\t{}\t{}\t{}",
+                        window.id, window.app_id, window.has_preview, window.title
+                    );
+                }
+            }
+            response => return exit_code(response),
+        },
+        Commands::Activate { id, app_id, title } => {
+            let selector = match (id, app_id, title) {
+                (Some(id), _, _) => WindowSelector::Id(*id),
+                (_, Some(app_id), _) => WindowSelector::AppId(app_id.clone()),
+                (_, _, Some(title)) => WindowSelector::Title(title.clone()),
+                _ => unreachable!("clap requires one selector"),
+            };
+
+            return exit_code(send_command(&ipc, IpcCommand::Activate(selector)).await);
+        }
+        Commands::Subscribe => {
+            let mut stdout = io::stdout().lock();
+            let result = ipc
+                .subscribe(|event| {
+                    let line = match serde_json::to_string(&event) {
+                        Ok(line) => line,
+                        Err(err) => {
+                            tracing::warn!("Failed to serialize event: {}", err);
+                            return ControlFlow::Continue(());
+                        }
+                    };
+
+                    match writeln!(stdout, "{}", line) {
+                        Ok(()) => ControlFlow::Continue(()),
+                        // the reader went away, e.g. `alttabway subscribe | head -1`
+                        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {
+                            ControlFlow::Break(())
+                        }
+                        Err(err) => {
+                            tracing::warn!("Failed to write event: {}", err);
+                            ControlFlow::Break(())
+                        }
+                    }
+                })
+                .await;
+
+            if let Err(err) = result {
+                report_ipc_error(err);
+                return ExitCode::FAILURE;
+            }
+        }
+        Commands::Status { json } => match send_command(&ipc, IpcCommand::Status).await {
+            Some(IpcCommandResponse::Status(status)) => match json {
+                true => match serde_json::to_string_pretty(&status) {
+                    Ok(status) => println!("{}", status),
+                    Err(err) => tracing::warn!("Failed to serialize status: {}", err),
+                },
+                false => print_status(&status),
+            },
+            response => return exit_code(response),
+        },
+        Commands::Confirm => return exit_code(send_command(&ipc, IpcCommand::Confirm).await),
+        Commands::Cancel => return exit_code(send_command(&ipc, IpcCommand::Cancel).await),
+        Commands::Select { index } => {
+            return exit_code(send_command(&ipc, IpcCommand::Select(*index)).await);
+        }
+        Commands::Preview {
+            id,
+            output,
+            width,
+            height,
+        } => match send_command(&ipc, IpcCommand::Preview { id: *id }).await {
+            Some(IpcCommandResponse::Preview(preview)) => {
+                if let Err(err) = write_preview(preview, (*width, *height), output.as_deref()) {
+                    tracing::error!("Failed to write preview: {}", err);
+                    return ExitCode::FAILURE;
+                }
+            }
+            response => return exit_code(response),
+        },
+        Commands::Quit => return exit_code(send_command(&ipc, IpcCommand::Quit).await),
+        Commands::Reload => return exit_code(send_command(&ipc, IpcCommand::Reload).await),
     }
+
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,266 @@
+use anyhow::{Result, bail};
+use smithay_client_toolkit::reexports::client::protocol::wl_shm::Format;
+
+/// Layout of a captured shm buffer
+#[derive(Debug, Clone, Copy)]
+pub struct FrameLayout {
+    pub width: u32,
+    pub height: u32,
+    /// Bytes per row, which may include padding after the pixels
+    pub stride: u32,
+    pub format: Format,
+    /// The rows are stored bottom to top
+    pub y_invert: bool,
+}
+
+pub fn bytes_per_pixel(format: Format) -> Option<u32> {
+    Some(match format {
+        Format::Argb8888
+        | Format::Xrgb8888
+        | Format::Abgr8888
+        | Format::Xbgr8888
+        | Format::Argb2101010
+        | Format::Xrgb2101010
+        | Format::Abgr2101010
+        | Format::Xbgr2101010 => 4,
+        Format::Rgb888 | Format::Bgr888 => 3,
+        Format::Rgb565 | Format::Bgr565 => 2,
+        _ => return None,
+    })
+}
+
+pub fn is_supported(format: Format) -> bool {
+    bytes_per_pixel(format).is_some()
+}
+
+fn u32_le(pixel: &[u8]) -> u32 {
+    u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]])
+}
+
+fn u16_le(pixel: &[u8]) -> u32 {
+    u16::from_le_bytes([pixel[0], pixel[1]]) as u32
+}
+
+/// Scales a channel with `bits` bits to 8 bits
+fn to_u8(value: u32, bits: u32) -> u8 {
+    let max = (1 << bits) - 1;
+    ((value & max) * 255 / max) as u8
+}
+
+/// Converts a single pixel to RGB. wl_shm formats name their channels from the most to the
+/// least significant bits of a little endian value.
+fn convert_pixel(format: Format, pixel: &[u8]) -> [u8; 3] {
+    match format {
+        Format::Argb8888 | Format::Xrgb8888 => [pixel[2], pixel[1], pixel[0]],
+        Format::Abgr8888 | Format::Xbgr8888 => [pixel[0], pixel[1], pixel[2]],
+        Format::Rgb888 => [pixel[2], pixel[1], pixel[0]],
+        Format::Bgr888 => [pixel[0], pixel[1], pixel[2]],
+        Format::Argb2101010 | Format::Xrgb2101010 => {
+            let value = u32_le(pixel);
+            [
+                to_u8(value >> 20, 10),
+                to_u8(value >> 10, 10),
+                to_u8(value, 10),
+            ]
+        }
+        Format::Abgr2101010 | Format::Xbgr2101010 => {
+            let value = u32_le(pixel);
+            [
+                to_u8(value, 10),
+                to_u8(value >> 10, 10),
+                to_u8(value >> 20, 10),
+            ]
+        }
+        Format::Rgb565 => {
+            let value = u16_le(pixel);
+            [to_u8(value >> 11, 5), to_u8(value >> 5, 6), to_u8(value, 5)]
+        }
+        Format::Bgr565 => {
+            let value = u16_le(pixel);
+            [to_u8(value, 5), to_u8(value >> 5, 6), to_u8(value >> 11, 5)]
+        }
+        _ => unreachable!("unsupported formats are rejected before converting"),
+    }
+}
+
+/// Converts a captured buffer to tightly packed RGB rows from top to bottom
+pub fn to_rgb(data: &[u8], layout: &FrameLayout) -> Result<Vec<u8>> {
+    let Some(bytes_per_pixel) = bytes_per_pixel(layout.format) else {
+        bail!("unsupported pixel format {:?}", layout.format);
+    };
+
+    let (width, height, stride) = (
+        layout.width as usize,
+        layout.height as usize,
+        layout.stride as usize,
+    );
+    let row_len = width * bytes_per_pixel as usize;
+
+    if stride < row_len {
+        bail!("stride {} is too small for {} pixels", stride, width);
+    }
+    if height > 0 && data.len() < stride * (height - 1) + row_len {
+        bail!("buffer is too small for {}x{} pixels", width, height);
+    }
+
+    let mut rgb = Vec::with_capacity(width * height * 3);
+
+    for row in 0..height {
+        let row = if layout.y_invert {
+            height - 1 - row
+        } else {
+            row
+        };
+        let start = row * stride;
+
+        for pixel in data[start..start + row_len].chunks_exact(bytes_per_pixel as usize) {
+            rgb.extend(convert_pixel(layout.format, pixel));
+        }
+    }
+
+    Ok(rgb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(format: Format, width: u32, height: u32) -> FrameLayout {
+        FrameLayout {
+            width,
+            height,
+            stride: width * bytes_per_pixel(format).unwrap(),
+            format,
+            y_invert: false,
+        }
+    }
+
+    fn convert(format: Format, data: &[u8]) -> Vec<u8> {
+        let pixels = data.len() as u32 / bytes_per_pixel(format).unwrap();
+        to_rgb(data, &layout(format, pixels, 1)).unwrap()
+    }
+
+    #[test]
+    fn argb8888() {
+        assert_eq!(
+            convert(Format::Argb8888, &[0x30, 0x20, 0x10, 0xff]),
+            [0x10, 0x20, 0x30]
+        );
+        assert_eq!(
+            convert(Format::Xrgb8888, &[0x30, 0x20, 0x10, 0x00]),
+            [0x10, 0x20, 0x30]
+        );
+    }
+
+    #[test]
+    fn abgr8888() {
+        assert_eq!(
+            convert(Format::Abgr8888, &[0x10, 0x20, 0x30, 0xff]),
+            [0x10, 0x20, 0x30]
+        );
+        assert_eq!(
+            convert(Format::Xbgr8888, &[0x10, 0x20, 0x30, 0x00]),
+            [0x10, 0x20, 0x30]
+        );
+    }
+
+    #[test]
+    fn rgb888() {
+        assert_eq!(
+            convert(Format::Rgb888, &[0x30, 0x20, 0x10]),
+            [0x10, 0x20, 0x30]
+        );
+        assert_eq!(
+            convert(Format::Bgr888, &[0x10, 0x20, 0x30]),
+            [0x10, 0x20, 0x30]
+        );
+    }
+
+    #[test]
+    fn xrgb2101010() {
+        // red at full intensity, green at half, blue off, alpha bits set
+        let value: u32 = (0b11 << 30) | (0x3ff << 20) | (0x200 << 10);
+        let expected = [0xff, 0x7f, 0x00];
+        assert_eq!(convert(Format::Xrgb2101010, &value.to_le_bytes()), expected);
+        assert_eq!(convert(Format::Argb2101010, &value.to_le_bytes()), expected);
+    }
+
+    #[test]
+    fn xbgr2101010() {
+        let value: u32 = (0b11 << 30) | (0x3ff << 20) | (0x200 << 10);
+        let expected = [0x00, 0x7f, 0xff];
+        assert_eq!(convert(Format::Xbgr2101010, &value.to_le_bytes()), expected);
+        assert_eq!(convert(Format::Abgr2101010, &value.to_le_bytes()), expected);
+    }
+
+    #[test]
+    fn rgb565() {
+        let value: u16 = (0x1f << 11) | (0x20 << 5);
+        assert_eq!(
+            convert(Format::Rgb565, &value.to_le_bytes()),
+            [0xff, 0x81, 0x00]
+        );
+        assert_eq!(
+            convert(Format::Bgr565, &value.to_le_bytes()),
+            [0x00, 0x81, 0xff]
+        );
+    }
+
+    #[test]
+    fn padded_stride() {
+        let data = [
+            1, 2, 3, 0, 4, 5, 6, 0, 0xee, 0xee, // first row and padding
+            7, 8, 9, 0, 10, 11, 12, 0, 0xee, 0xee, // second row and padding
+        ];
+        let layout = FrameLayout {
+            stride: 10,
+            ..layout(Format::Xbgr8888, 2, 2)
+        };
+
+        assert_eq!(
+            to_rgb(&data, &layout).unwrap(),
+            [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]
+        );
+    }
+
+    #[test]
+    fn last_row_without_padding() {
+        let data = [1, 2, 3, 0xee, 4, 5, 6];
+        let layout = FrameLayout {
+            stride: 4,
+            ..layout(Format::Bgr888, 1, 2)
+        };
+
+        assert_eq!(to_rgb(&data, &layout).unwrap(), [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn y_invert() {
+        let data = [1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let layout = FrameLayout {
+            y_invert: true,
+            ..layout(Format::Bgr888, 1, 3)
+        };
+
+        assert_eq!(to_rgb(&data, &layout).unwrap(), [7, 8, 9, 4, 5, 6, 1, 2, 3]);
+    }
+
+    #[test]
+    fn rejects_invalid_layouts() {
+        let data = [0; 16];
+
+        let small_stride = FrameLayout {
+            stride: 4,
+            ..layout(Format::Xrgb8888, 2, 2)
+        };
+        assert!(to_rgb(&data, &small_stride).is_err());
+
+        assert!(to_rgb(&data, &layout(Format::Xrgb8888, 4, 2)).is_err());
+
+        let unsupported = FrameLayout {
+            format: Format::Yuyv,
+            ..layout(Format::Xrgb8888, 2, 2)
+        };
+        assert!(to_rgb(&data, &unsupported).is_err());
+    }
+}
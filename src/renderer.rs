@@ -27,6 +27,8 @@ pub trait Renderer {
     ) -> anyhow::Result<()>;
     fn destroy_surface(&mut self, wayland_client: &mut WaylandClient) -> anyhow::Result<()>;
     fn render(&mut self, wayland_client: &mut WaylandClient, gui: &mut Gui) -> anyhow::Result<()>;
+    /// Human readable description of the backend, e.g. for `alttabway status`
+    fn describe(&self) -> String;
 }
 
 struct WgpuState {
@@ -43,7 +45,8 @@ struct WgpuSurface {
 
 pub struct WgpuRenderer {
     state: Arc<WgpuState>,
-    maybe_surface: Arc<Mutex<Option<WgpuSurface>>>,
+    /// One surface per output the switcher is shown on, all showing the same content
+    surfaces: Arc<Mutex<Vec<WgpuSurface>>>,
     egui_renderer: Option<egui_wgpu::Renderer>,
 }
 
@@ -94,7 +97,7 @@ impl WgpuRenderer {
 
         let renderer = Self {
             state: Arc::new(state),
-            maybe_surface: Arc::new(Mutex::new(None)),
+            surfaces: Arc::new(Mutex::new(Vec::new())),
             egui_renderer: None,
         };
 
@@ -164,17 +167,21 @@ impl Renderer for WgpuRenderer {
         let raw_handles = wayland_client.get_raw_handles()?;
 
         let state = self.state.clone();
-        let maybe_surface = self.maybe_surface.clone();
+        let surfaces = self.surfaces.clone();
 
         // TODO: Fix race condition that is a thing here
         tokio::spawn(async move {
-            let Ok(wgpu_surface) = Self::init_wgpu_surface(&state, raw_handles, width, height)
-            else {
+            let wgpu_surfaces: anyhow::Result<Vec<_>> = raw_handles
+                .into_iter()
+                .map(|raw_handles| Self::init_wgpu_surface(&state, raw_handles, width, height))
+                .collect();
+
+            let Ok(wgpu_surfaces) = wgpu_surfaces else {
                 tracing::warn!("Critical error: failed to create wgpu surface.");
                 return;
             };
 
-            *maybe_surface.lock().unwrap() = Some(wgpu_surface);
+            *surfaces.lock().unwrap() = wgpu_surfaces;
 
             request_paint.send(()).unwrap();
         });
@@ -182,29 +189,33 @@ impl Renderer for WgpuRenderer {
     }
 
     fn destroy_surface(&mut self, _: &mut WaylandClient) -> anyhow::Result<()> {
-        self.maybe_surface.lock().unwrap().take();
+        self.surfaces.lock().unwrap().clear();
         Ok(())
     }
 
+    fn describe(&self) -> String {
+        let adapter_info = self.state.adapter.get_info();
+        format!("{} ({})", adapter_info.backend, adapter_info.name)
+    }
+
     fn render(&mut self, _: &mut WaylandClient, gui: &mut Gui) -> anyhow::Result<()> {
         let _span = tracing::trace_span!("Paint").entered();
 
-        let Some(WgpuSurface {
-            surface,
-            surface_config,
-        }) = &mut *self.maybe_surface.lock().unwrap()
-        else {
+        let surfaces = self.surfaces.lock().unwrap();
+        let Some(WgpuSurface { surface_config, .. }) = surfaces.first() else {
             tracing::warn!("Render called but no surface!");
             return Ok(());
         };
 
-        let CurrentSurfaceTexture::Success(surface_texture) = surface.get_current_texture() else {
-            return Ok(());
-        };
-
-        let view = surface_texture
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        // mirrored surfaces have the same size as the main one
+        let mut surface_textures = Vec::new();
+        for WgpuSurface { surface, .. } in surfaces.iter() {
+            let CurrentSurfaceTexture::Success(surface_texture) = surface.get_current_texture()
+            else {
+                return Ok(());
+            };
+            surface_textures.push(surface_texture);
+        }
 
         let width = surface_config.width;
         let height = surface_config.height;
@@ -218,7 +229,7 @@ impl Renderer for WgpuRenderer {
 
         let screen_descriptor = egui_wgpu::ScreenDescriptor {
             size_in_pixels: [width, height],
-            pixels_per_point: 1.0,
+            pixels_per_point: gui.get_scale(),
         };
 
         let (textures_delta, clipped_primitives) = gui.get_output(width as f32, height as f32);
@@ -247,9 +258,13 @@ impl Renderer for WgpuRenderer {
             &screen_descriptor,
         );
 
-        {
+        for surface_texture in &surface_textures {
             tracing::trace!("Beginning render pass");
 
+            let view = surface_texture
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
+
             let render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
@@ -288,7 +303,9 @@ impl Renderer for WgpuRenderer {
         self.state.queue.submit(iter::once(encoder.finish()));
 
         tracing::trace!("Presenting output");
-        surface_texture.present();
+        for surface_texture in surface_textures {
+            surface_texture.present();
+        }
 
         tracing::trace!("Completed");
         Ok(())
@@ -334,6 +351,10 @@ impl Renderer for SoftwareRenderer {
         Ok(())
     }
 
+    fn describe(&self) -> String {
+        "Software".into()
+    }
+
     fn render(&mut self, wayland_client: &mut WaylandClient, gui: &mut Gui) -> anyhow::Result<()> {
         tracing::trace!("render requested!!");
         let Some(buffer) = &mut self.buffer else {
@@ -350,9 +371,13 @@ impl Renderer for SoftwareRenderer {
             let (pixelbuf, _): (&mut [[u8; 4]], &mut [u8]) = pixels.as_chunks_mut();
             let mut buffer_ref = BufferMutRef::new(pixelbuf, width as usize, height as usize);
 
-            self.sw_render
-                .render(&mut buffer_ref, &clipped_primitives, &textures_delta, 1.0);
-        });
+            self.sw_render.render(
+                &mut buffer_ref,
+                &clipped_primitives,
+                &textures_delta,
+                gui.get_scale(),
+            );
+        })?;
 
         wayland_client.update_surface_buffer(buffer);
         tracing::trace!("render complete!!");
@@ -0,0 +1,109 @@
+use anyhow::{Context, Result};
+use smithay_client_toolkit::{
+    reexports::client::protocol::wl_shm::Format,
+    shm::{
+        Shm,
+        slot::{Buffer, SlotPool},
+    },
+};
+
+use crate::wayland_client_event::CapturedBuffer;
+
+/// Enough for a maximized window on a 1080p output, the pool grows past this on demand
+const INITIAL_POOL_SIZE: usize = 1920 * 1080 * 4;
+
+/// How many captured buffers are kept around to capture into again
+const MAX_SPARE_BUFFERS: usize = 4;
+
+/// Shared memory backing the switcher surface and all captures.
+///
+/// wl_shm pools can only ever grow, so once the switcher is hidden and nothing
+/// uses the pool anymore, [`ShmPool::trim`] swaps an oversized pool for a fresh one.
+#[derive(Debug)]
+pub struct ShmPool {
+    pool: SlotPool,
+    spare_buffers: Vec<CapturedBuffer>,
+}
+
+impl ShmPool {
+    pub fn new(shm: &Shm) -> Result<Self> {
+        Ok(Self {
+            pool: SlotPool::new(INITIAL_POOL_SIZE, shm)?,
+            spare_buffers: Vec::new(),
+        })
+    }
+
+    pub fn create_buffer(
+        &mut self,
+        width: i32,
+        height: i32,
+        stride: i32,
+        format: Format,
+    ) -> Result<Buffer> {
+        let (buffer, _) = self
+            .pool
+            .create_buffer(width, height, stride, format)
+            .context("could not create buffer from pool!")?;
+        Ok(buffer)
+    }
+
+    /// Returns a buffer to capture into, reusing a previous capture buffer with the same layout
+    pub fn capture_buffer(
+        &mut self,
+        width: i32,
+        height: i32,
+        stride: i32,
+        format: Format,
+    ) -> Result<Buffer> {
+        let spare = self.spare_buffers.iter().position(|spare| {
+            let layout = &spare.layout;
+            (layout.width, layout.height, layout.stride, layout.format)
+                == (width as u32, height as u32, stride as u32, format)
+        });
+
+        match spare {
+            Some(index) => Ok(self.spare_buffers.swap_remove(index).buffer),
+            None => self.create_buffer(width, height, stride, format),
+        }
+    }
+
+    /// Keeps a capture buffer that has been read so that a later capture can reuse it.
+    /// Buffers of a pool that has since been trimmed are dropped.
+    pub fn recycle(&mut self, captured: CapturedBuffer) {
+        if self.canvas(&captured.buffer).is_none() {
+            return;
+        }
+        if self.spare_buffers.len() == MAX_SPARE_BUFFERS {
+            self.spare_buffers.remove(0);
+        }
+        self.spare_buffers.push(captured);
+    }
+
+    pub fn canvas(&mut self, buffer: &Buffer) -> Option<&mut [u8]> {
+        buffer.canvas(&mut self.pool)
+    }
+
+    /// Size of the pool in bytes
+    pub fn len(&self) -> usize {
+        self.pool.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pool.len() == 0
+    }
+
+    /// Replaces the pool with one of the initial size if it has grown past it.
+    ///
+    /// Buffers created from the old pool can't be written to afterwards, so
+    /// this must only be called when no buffers other than the spare ones are in use.
+    pub fn trim(&mut self, shm: &Shm) -> Result<()> {
+        if self.pool.len() <= INITIAL_POOL_SIZE {
+            return Ok(());
+        }
+
+        tracing::debug!("Trimming shm pool of {} KiB", self.pool.len() / 1024);
+        self.spare_buffers.clear();
+        self.pool = SlotPool::new(INITIAL_POOL_SIZE, shm)?;
+        Ok(())
+    }
+}
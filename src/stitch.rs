@@ -0,0 +1,308 @@
+use smithay_client_toolkit::reexports::client::protocol::wl_output::Transform;
+
+/// RGB pixels of a window region that was captured from a single output
+#[derive(Debug)]
+pub struct RgbPiece {
+    pub pixels: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    /// Position and size of the piece within the window, in logical coordinates
+    pub region: (i32, i32, i32, i32),
+    /// Transform of the output the piece was captured from
+    pub transform: Transform,
+}
+
+impl RgbPiece {
+    /// Width and height of the piece once the output transform has been undone
+    fn oriented_size(&self) -> (u32, u32) {
+        match self.transform {
+            Transform::_90 | Transform::_270 | Transform::Flipped90 | Transform::Flipped270 => {
+                (self.height, self.width)
+            }
+            _ => (self.width, self.height),
+        }
+    }
+
+    fn scale(&self) -> f64 {
+        let (_, _, region_width, _) = self.region;
+        self.oriented_size().0 as f64 / region_width.max(1) as f64
+    }
+
+    /// Maps a pixel of the upright piece to the captured buffer. Outputs present their content
+    /// rotated counter-clockwise by the transform, after flipping it for the flipped variants.
+    fn buffer_position(&self, x: u32, y: u32) -> (u32, u32) {
+        let (width, height) = self.oriented_size();
+        let (right, bottom) = (width - 1 - x, height - 1 - y);
+
+        match self.transform {
+            Transform::_90 => (y, right),
+            Transform::_180 => (right, bottom),
+            Transform::_270 => (bottom, x),
+            Transform::Flipped => (right, y),
+            Transform::Flipped90 => (y, x),
+            Transform::Flipped180 => (x, bottom),
+            Transform::Flipped270 => (bottom, right),
+            _ => (x, y),
+        }
+    }
+}
+
+/// Composes the pieces of a window captured from different outputs into one RGB image and
+/// returns it along with its width. The image uses the highest scale among the outputs, pieces
+/// from outputs with a lower scale are upscaled to match.
+pub fn stitch(window_size: (i32, i32), pieces: Vec<RgbPiece>) -> (Vec<u8>, u32) {
+    let (window_width, window_height) = window_size;
+
+    // the common case of a window on a single, untransformed output
+    if let [piece] = pieces.as_slice()
+        && piece.transform == Transform::Normal
+        && piece.region == (0, 0, window_width, window_height)
+    {
+        let width = piece.width;
+        return (pieces.into_iter().next().unwrap().pixels, width);
+    }
+
+    let scale = pieces.iter().map(RgbPiece::scale).fold(1.0, f64::max);
+    let to_pixels = |logical: i32| (logical as f64 * scale).round().max(0.0) as u32;
+
+    let (width, height) = (to_pixels(window_width), to_pixels(window_height));
+    let mut pixels = vec![0; width as usize * height as usize * 3];
+
+    for piece in &pieces {
+        let (x, y, region_width, region_height) = piece.region;
+        let (left, top) = (to_pixels(x).min(width), to_pixels(y).min(height));
+        let right = to_pixels(x + region_width).min(width);
+        let bottom = to_pixels(y + region_height).min(height);
+
+        if left >= right || top >= bottom {
+            continue;
+        }
+
+        let (piece_width, piece_height) = piece.oriented_size();
+
+        for dst_y in top..bottom {
+            let src_y = (dst_y - top) as u64 * piece_height as u64 / (bottom - top) as u64;
+
+            for dst_x in left..right {
+                let src_x = (dst_x - left) as u64 * piece_width as u64 / (right - left) as u64;
+                let (buffer_x, buffer_y) = piece.buffer_position(src_x as u32, src_y as u32);
+
+                let src = (buffer_y as usize * piece.width as usize + buffer_x as usize) * 3;
+                let dst = (dst_y as usize * width as usize + dst_x as usize) * 3;
+
+                if let Some(pixel) = piece.pixels.get(src..src + 3) {
+                    pixels[dst..dst + 3].copy_from_slice(pixel);
+                }
+            }
+        }
+    }
+
+    (pixels, width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSFORMS: [Transform; 8] = [
+        Transform::Normal,
+        Transform::_90,
+        Transform::_180,
+        Transform::_270,
+        Transform::Flipped,
+        Transform::Flipped90,
+        Transform::Flipped180,
+        Transform::Flipped270,
+    ];
+
+    /// An RGB image where every pixel is unique, so misplaced pixels are caught
+    fn image(width: u32, height: u32, seed: u8) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| [i as u8, (i >> 8) as u8, seed])
+            .collect()
+    }
+
+    fn pixel(pixels: &[u8], width: u32, x: u32, y: u32) -> &[u8] {
+        let index = (y * width + x) as usize * 3;
+        &pixels[index..index + 3]
+    }
+
+    /// Renders an upright image the way a compositor lays it out in the buffer of an output with
+    /// this transform, as in wlroots' transform matrices
+    fn to_buffer(upright: &[u8], width: u32, height: u32, transform: Transform) -> (Vec<u8>, u32) {
+        let (a, b, c, d): (i32, i32, i32, i32) = match transform {
+            Transform::_90 => (0, 1, -1, 0),
+            Transform::_180 => (-1, 0, 0, -1),
+            Transform::_270 => (0, -1, 1, 0),
+            Transform::Flipped => (-1, 0, 0, 1),
+            Transform::Flipped90 => (0, 1, 1, 0),
+            Transform::Flipped180 => (1, 0, 0, -1),
+            Transform::Flipped270 => (0, -1, -1, 0),
+            _ => (1, 0, 0, 1),
+        };
+        let (buffer_width, buffer_height) = match b != 0 {
+            true => (height, width),
+            false => (width, height),
+        };
+
+        let mut buffer = vec![0; upright.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let (x, y) = (x as i32, y as i32);
+                // negative coordinates count from the far edge of the buffer
+                let wrap = |value: i32, size: u32| value.rem_euclid(size as i32) as u32;
+                let buffer_x = wrap(a * x + b * y - (a + b < 0) as i32, buffer_width);
+                let buffer_y = wrap(c * x + d * y - (c + d < 0) as i32, buffer_height);
+
+                let dst = ((buffer_y * buffer_width + buffer_x) * 3) as usize;
+                buffer[dst..dst + 3].copy_from_slice(pixel(upright, width, x as u32, y as u32));
+            }
+        }
+        (buffer, buffer_width)
+    }
+
+    fn piece(
+        pixels: Vec<u8>,
+        (width, height): (u32, u32),
+        region: (i32, i32, i32, i32),
+        transform: Transform,
+    ) -> RgbPiece {
+        RgbPiece {
+            pixels,
+            width,
+            height,
+            region,
+            transform,
+        }
+    }
+
+    #[test]
+    fn rotated_90_by_hand() {
+        // a 2x1 window [A B] rotated counter-clockwise puts B on top of A
+        let buffer = vec![2, 2, 2, 1, 1, 1];
+        let (pixels, width) = stitch(
+            (2, 1),
+            vec![piece(buffer, (1, 2), (0, 0, 2, 1), Transform::_90)],
+        );
+        assert_eq!(width, 2);
+        assert_eq!(pixels, [1, 1, 1, 2, 2, 2]);
+    }
+
+    #[test]
+    fn undoes_every_transform() {
+        let (width, height) = (5, 3);
+        let upright = image(width, height, 0);
+
+        for transform in TRANSFORMS {
+            let (buffer, buffer_width) = to_buffer(&upright, width, height, transform);
+            let buffer_height = width * height / buffer_width;
+            let (pixels, stitched_width) = stitch(
+                (width as i32, height as i32),
+                vec![piece(
+                    buffer,
+                    (buffer_width, buffer_height),
+                    (0, 0, width as i32, height as i32),
+                    transform,
+                )],
+            );
+
+            assert_eq!(stitched_width, width, "{:?}", transform);
+            assert_eq!(pixels, upright, "{:?}", transform);
+        }
+    }
+
+    #[test]
+    fn transformed_pieces_side_by_side() {
+        let left = image(2, 2, 1);
+        let right = image(3, 2, 2);
+        let (right_buffer, right_buffer_width) = to_buffer(&right, 3, 2, Transform::_270);
+
+        let (pixels, width) = stitch(
+            (5, 2),
+            vec![
+                piece(left.clone(), (2, 2), (0, 0, 2, 2), Transform::Normal),
+                piece(
+                    right_buffer,
+                    (right_buffer_width, 3),
+                    (2, 0, 3, 2),
+                    Transform::_270,
+                ),
+            ],
+        );
+
+        assert_eq!(width, 5);
+        for y in 0..2 {
+            for x in 0..2 {
+                assert_eq!(pixel(&pixels, 5, x, y), pixel(&left, 2, x, y));
+            }
+            for x in 0..3 {
+                assert_eq!(pixel(&pixels, 5, x + 2, y), pixel(&right, 3, x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn upscales_to_the_highest_scale() {
+        // the left output has a scale of 1, the right one a scale of 2
+        let left = image(2, 2, 1);
+        let right = image(4, 4, 2);
+
+        let (pixels, width) = stitch(
+            (4, 2),
+            vec![
+                piece(left.clone(), (2, 2), (0, 0, 2, 2), Transform::Normal),
+                piece(right.clone(), (4, 4), (2, 0, 2, 2), Transform::Normal),
+            ],
+        );
+
+        assert_eq!((width, pixels.len()), (8, 8 * 4 * 3));
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!(pixel(&pixels, 8, x, y), pixel(&left, 2, x / 2, y / 2));
+                assert_eq!(pixel(&pixels, 8, x + 4, y), pixel(&right, 4, x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn fractional_scale() {
+        // the left output has a scale of 1.5, the right one a scale of 1
+        let left = image(3, 3, 1);
+        let right = image(2, 2, 2);
+
+        let (pixels, width) = stitch(
+            (4, 2),
+            vec![
+                piece(left.clone(), (3, 3), (0, 0, 2, 2), Transform::Normal),
+                piece(right.clone(), (2, 2), (2, 0, 2, 2), Transform::Normal),
+            ],
+        );
+
+        assert_eq!((width, pixels.len()), (6, 6 * 3 * 3));
+        for y in 0..3 {
+            for x in 0..3 {
+                assert_eq!(pixel(&pixels, 6, x, y), pixel(&left, 3, x, y));
+                assert_eq!(
+                    pixel(&pixels, 6, x + 3, y),
+                    pixel(&right, 2, x * 2 / 3, y * 2 / 3)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn pieces_outside_the_window_are_clipped() {
+        let (pixels, width) = stitch(
+            (2, 2),
+            vec![
+                piece(image(2, 2, 1), (2, 2), (0, 0, 2, 2), Transform::Normal),
+                piece(image(2, 2, 2), (2, 2), (2, 0, 2, 2), Transform::Normal),
+                piece(image(4, 4, 3), (4, 4), (1, 1, 4, 4), Transform::Normal),
+            ],
+        );
+
+        assert_eq!((width, pixels.len()), (2, 2 * 2 * 3));
+        assert_eq!(pixel(&pixels, 2, 0, 0), pixel(&image(2, 2, 1), 2, 0, 0));
+        assert_eq!(pixel(&pixels, 2, 1, 1), pixel(&image(4, 4, 3), 4, 0, 0));
+    }
+}
@@ -4,9 +4,11 @@ use raw_window_handle::{
 };
 use smithay_client_toolkit::{
     compositor::{CompositorHandler, CompositorState},
-    delegate_compositor, delegate_keyboard, delegate_layer, delegate_output, delegate_pointer,
-    delegate_registry, delegate_seat, delegate_shm,
-    output::{OutputHandler, OutputState},
+    delegate_compositor, delegate_foreign_toplevel_list, delegate_keyboard, delegate_layer,
+    delegate_output, delegate_pointer, delegate_registry, delegate_seat, delegate_shm,
+    foreign_toplevel_list::{ForeignToplevelList, ForeignToplevelListHandler},
+    globals::ProvidesBoundGlobal,
+    output::{OutputHandler, OutputInfo, OutputState},
     reexports::{
         client::{
             self, Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum,
@@ -20,11 +22,33 @@ use smithay_client_toolkit::{
                 wl_surface::WlSurface,
             },
         },
+        protocols::{
+            ext::{
+                foreign_toplevel_list::v1::client::ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+                image_capture_source::v1::client::{
+                    ext_foreign_toplevel_image_capture_source_manager_v1::ExtForeignToplevelImageCaptureSourceManagerV1,
+                    ext_image_capture_source_v1::ExtImageCaptureSourceV1,
+                },
+                image_copy_capture::v1::client::{
+                    ext_image_copy_capture_frame_v1::{self, ExtImageCopyCaptureFrameV1},
+                    ext_image_copy_capture_manager_v1::{self, ExtImageCopyCaptureManagerV1},
+                    ext_image_copy_capture_session_v1::{self, ExtImageCopyCaptureSessionV1},
+                },
+            },
+            wp::{
+                fractional_scale::v1::client::{
+                    wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+                    wp_fractional_scale_v1::{self, WpFractionalScaleV1},
+                },
+                viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter},
+            },
+        },
         protocols_wlr::{
             foreign_toplevel::v1::client::{
                 zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
                 zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
             },
+            layer_shell::v1::client::zwlr_layer_shell_v1::ZwlrLayerShellV1,
             screencopy::v1::client::{
                 zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1},
                 zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
@@ -35,9 +59,10 @@ use smithay_client_toolkit::{
     registry_handlers,
     seat::{
         Capability, SeatHandler, SeatState,
-        keyboard::{KeyEvent, KeyboardHandler, Keysym, Modifiers},
+        keyboard::{KeyEvent, KeyboardData, KeyboardHandler, Keysym, Modifiers},
         pointer::{
-            CursorIcon, PointerEvent, PointerEventKind, PointerHandler, ThemeSpec, ThemedPointer,
+            CursorIcon, PointerData, PointerEvent, PointerEventKind, PointerHandler, ThemeSpec,
+            ThemedPointer,
         },
     },
     shell::{
@@ -47,16 +72,30 @@ use smithay_client_toolkit::{
             LayerSurfaceConfigure,
         },
     },
-    shm::{
-        Shm, ShmHandler,
-        slot::{Buffer, SlotPool},
-    },
+    shm::{Shm, ShmHandler, slot::Buffer},
 };
-use std::{collections::HashMap, ffi::c_void, ptr::NonNull};
+use std::{collections::HashMap, ffi::c_void, mem, ptr::NonNull};
 use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 use tracing::{debug, warn};
 
-use crate::wayland_client_event::WaylandClientEvent;
+use crate::{
+    hyprland_toplevel_export::client::{
+        hyprland_toplevel_export_frame_v1::{self, HyprlandToplevelExportFrameV1},
+        hyprland_toplevel_export_manager_v1::HyprlandToplevelExportManagerV1,
+    },
+    pixel_format::{self, FrameLayout},
+    shm_pool::ShmPool,
+    wayland_client_event::{CapturePiece, CapturedBuffer, ToplevelState, WaylandClientEvent},
+};
+
+/// Where a captured output region ends up in the window it belongs to
+#[derive(Debug)]
+struct RegionPlacement {
+    region: (i32, i32, i32, i32),
+    transform: Transform,
+    /// The [`RegionCapture::generation`] the region was captured for
+    generation: u64,
+}
 
 #[derive(Default, Debug)]
 pub struct ScreencopyFrameState {
@@ -66,6 +105,8 @@ pub struct ScreencopyFrameState {
     stride: i32,
     format: Option<Format>,
     buffer: Option<Buffer>,
+    y_invert: bool,
+    placement: Option<RegionPlacement>,
 }
 
 impl ScreencopyFrameState {
@@ -100,12 +141,114 @@ impl ScreencopyFrameState {
             None
         }
     }
+
+    /// Allocates a buffer matching the announced buffer details for the frame to be copied into
+    fn create_buffer(&mut self, pool: &mut ShmPool) -> anyhow::Result<&Buffer> {
+        let (width, height, stride, format) = self
+            .get_buffer_details()
+            .context("no supported buffer format")?;
+
+        tracing::debug!("widthxheight (stride) {}x{} ({})", width, height, stride);
+
+        let buffer = pool.capture_buffer(width, height, stride, format)?;
+
+        Ok(self.buffer.insert(buffer))
+    }
+
+    fn into_captured(self) -> Option<(u32, CapturedBuffer)> {
+        let ScreencopyFrameState {
+            id,
+            width,
+            height,
+            stride,
+            format: Some(format),
+            buffer: Some(buffer),
+            y_invert,
+            ..
+        } = self
+        else {
+            return None;
+        };
+
+        let layout = FrameLayout {
+            width: width.try_into().ok()?,
+            height: height.try_into().ok()?,
+            stride: stride.try_into().ok()?,
+            format,
+            y_invert,
+        };
+        (id, CapturedBuffer { buffer, layout }).into()
+    }
+
+    fn into_done_event(self) -> Option<WaylandClientEvent> {
+        let (id, captured) = self.into_captured()?;
+        WaylandClientEvent::ScreencopyDone(id, captured).into()
+    }
+}
+
+/// A window captured by screencopying the regions of all outputs it is visible on
+#[derive(Debug)]
+struct RegionCapture {
+    /// Tells pieces of this capture apart from late pieces of an abandoned capture of the
+    /// same window
+    generation: u64,
+    size: (i32, i32),
+    expected_pieces: usize,
+    pieces: Vec<CapturePiece>,
+}
+
+/// A toplevel captured directly through ext-image-copy-capture
+#[derive(Debug)]
+struct ToplevelCaptureState {
+    source: ExtImageCaptureSourceV1,
+    frame_state: ScreencopyFrameState,
+}
+
+#[derive(Debug)]
+struct ToplevelCapture {
+    source_manager: ExtForeignToplevelImageCaptureSourceManagerV1,
+    copy_manager: ExtImageCopyCaptureManagerV1,
+}
+
+#[derive(Debug)]
+struct Toplevel {
+    handle: ZwlrForeignToplevelHandleV1,
+    title: String,
+    app_id: String,
+    /// Outputs the window is visible on
+    outputs: Vec<WlOutput>,
+    state: ToplevelState,
 }
 
 #[derive(Debug)]
 pub struct Surfaces {
     pub layer_surface: LayerSurface,
     pub wl_surface: WlSurface,
+    /// Only present on the main surface when it is scaled fractionally. Mirrored surfaces show the
+    /// buffers rendered at the main surface's scale, the viewport keeps their logical size right
+    /// but they look soft on outputs with a higher scale.
+    fractional_scale: Option<WpFractionalScaleV1>,
+    viewport: Option<WpViewport>,
+    /// Buffers can only be attached once the first configure has been received
+    configured: bool,
+}
+
+impl Surfaces {
+    fn destroy(self) {
+        if let Some(fractional_scale) = self.fractional_scale {
+            fractional_scale.destroy();
+        }
+        if let Some(viewport) = self.viewport {
+            viewport.destroy();
+        }
+    }
+}
+
+/// Globals needed to render at fractional scales, only used if both are available
+#[derive(Debug)]
+struct FractionalScale {
+    manager: WpFractionalScaleManagerV1,
+    viewporter: WpViewporter,
 }
 
 #[derive(Debug)]
@@ -118,18 +261,43 @@ pub struct WaylandClient {
     shm: Shm,
     connection: Connection,
     surfaces: Option<Surfaces>,
+    /// Copies of the switcher on other outputs, they show the same buffers but don't take keyboard focus
+    mirrored_surfaces: Vec<Surfaces>,
     wl_tx: UnboundedSender<WaylandClientEvent>,
     modifiers: Modifiers,
-    toplevel_windows: Vec<ZwlrForeignToplevelHandleV1>,
-    pool: SlotPool,
+    toplevel_windows: Vec<Toplevel>,
+    pool: ShmPool,
 
     screencopy_manager: ZwlrScreencopyManagerV1,
     screencopy_frames: HashMap<ZwlrScreencopyFrameV1, ScreencopyFrameState>,
+    region_captures: HashMap<u32, RegionCapture>,
+    next_region_generation: u64,
+
+    hyprland_toplevel_export: Option<HyprlandToplevelExportManagerV1>,
+    hyprland_frames: HashMap<HyprlandToplevelExportFrameV1, ScreencopyFrameState>,
+
+    /// ext-foreign-toplevel-list handles, only used to capture toplevels directly
+    ext_toplevel_list: ForeignToplevelList,
+    toplevel_capture: Option<ToplevelCapture>,
+    toplevel_captures: HashMap<ExtImageCopyCaptureSessionV1, ToplevelCaptureState>,
 
-    themed_pointer: Option<ThemedPointer>,
+    /// One pointer per seat that has one
+    themed_pointers: Vec<ThemedPointer>,
     current_cursor: Option<CursorIcon>,
     requested_cursor: CursorIcon,
     current_output: Option<WlOutput>,
+
+    /// Name of the only seat to use, from the config
+    pinned_seat: Option<String>,
+    /// The seat that last interacted with the switcher, windows are activated on it
+    input_seat: Option<WlSeat>,
+
+    fractional_scale: Option<FractionalScale>,
+    /// Buffer pixels per logical pixel of our surface
+    surface_scale: f64,
+
+    /// Interface names and versions of the globals bound at startup
+    bound_globals: Vec<(String, u32)>,
 }
 
 pub struct RawHandles {
@@ -156,19 +324,76 @@ impl WaylandClient {
         let seat_state = SeatState::new(&globals, &qh);
 
         // Bind the foreign toplevel manager
-        let _toplevel_manager =
+        let toplevel_manager =
             globals.bind::<ZwlrForeignToplevelManagerV1, _, _>(&qh, 3..=3, ())?;
 
         // Bind screencopy manager
         let screencopy_manager = globals.bind::<ZwlrScreencopyManagerV1, _, _>(&qh, 1..=3, ())?;
 
+        // Capturing toplevels directly is optional, we fall back to screencopying their region.
+        // Hyprland's protocol is preferred as it can capture windows on other workspaces.
+        let hyprland_toplevel_export = globals
+            .bind::<HyprlandToplevelExportManagerV1, _, _>(&qh, 2..=2, ())
+            .ok();
+        let ext_toplevel_list = ForeignToplevelList::new(&globals, &qh);
+        let toplevel_capture = match (
+            globals.bind::<ExtForeignToplevelImageCaptureSourceManagerV1, _, _>(&qh, 1..=1, ()),
+            globals.bind::<ExtImageCopyCaptureManagerV1, _, _>(&qh, 1..=1, ()),
+        ) {
+            (Ok(source_manager), Ok(copy_manager)) => ToplevelCapture {
+                source_manager,
+                copy_manager,
+            }
+            .into(),
+            _ => None,
+        };
+
+        // Without fractional scaling we fall back to integer buffer scales
+        let fractional_scale = match (
+            globals.bind::<WpFractionalScaleManagerV1, _, _>(&qh, 1..=1, ()),
+            globals.bind::<WpViewporter, _, _>(&qh, 1..=1, ()),
+        ) {
+            (Ok(manager), Ok(viewporter)) => FractionalScale {
+                manager,
+                viewporter,
+            }
+            .into(),
+            _ => None,
+        };
+
         // Bind shared memory
         let shm = Shm::bind(&globals, &qh)?;
 
-        // TODO: dynamic resizing
-        let pool = SlotPool::new(1920 * 1920 * 4, &shm)?;
+        let pool = ShmPool::new(&shm)?;
         let output_state = OutputState::new(&globals, &qh);
         let current_output = output_state.outputs().next().clone();
+        // a guess until our surface is shown, used for the size of previews
+        let surface_scale = current_output
+            .as_ref()
+            .and_then(|output| output_state.info(output))
+            .map_or(1, |info| info.scale_factor) as f64;
+
+        let layer_shell_global =
+            ProvidesBoundGlobal::<ZwlrLayerShellV1, 1>::bound_global(&layer_shell)?;
+        let mut bound_globals: Vec<_> = [
+            Self::describe_global(compositor_state.wl_compositor()),
+            Self::describe_global(&layer_shell_global),
+            Self::describe_global(shm.wl_shm()),
+            Self::describe_global(&toplevel_manager),
+            Self::describe_global(&screencopy_manager),
+        ]
+        .into();
+        if let Some(manager) = &hyprland_toplevel_export {
+            bound_globals.push(Self::describe_global(manager));
+        }
+        if let Some(capture) = &toplevel_capture {
+            bound_globals.push(Self::describe_global(&capture.source_manager));
+            bound_globals.push(Self::describe_global(&capture.copy_manager));
+        }
+        if let Some(fractional_scale) = &fractional_scale {
+            bound_globals.push(Self::describe_global(&fractional_scale.manager));
+            bound_globals.push(Self::describe_global(&fractional_scale.viewporter));
+        }
 
         let wayland_app = Self {
             registry_state: RegistryState::new(&globals),
@@ -179,28 +404,71 @@ impl WaylandClient {
             seat_state,
             shm,
             surfaces: None,
+            mirrored_surfaces: Vec::new(),
             wl_tx,
             modifiers: Default::default(),
             toplevel_windows: Vec::new(),
             screencopy_manager,
             screencopy_frames: HashMap::new(),
+            region_captures: HashMap::new(),
+            next_region_generation: 0,
+            hyprland_toplevel_export,
+            hyprland_frames: HashMap::new(),
+            ext_toplevel_list,
+            toplevel_capture,
+            toplevel_captures: HashMap::new(),
             pool,
 
-            themed_pointer: None,
+            themed_pointers: Vec::new(),
             current_cursor: None,
             requested_cursor: CursorIcon::Default,
             current_output,
+            pinned_seat: None,
+            input_seat: None,
+            fractional_scale,
+            surface_scale,
+            bound_globals,
         };
 
         Ok((wayland_app, event_queue, wl_rx))
     }
 
+    /// Events are only dropped once the daemon stopped listening, e.g. while it reconnects
+    fn send_event(&self, event: WaylandClientEvent) {
+        if self.wl_tx.send(event).is_err() {
+            tracing::debug!("Dropping wayland client event, nothing is listening");
+        }
+    }
+
+    fn describe_global(proxy: &impl Proxy) -> (String, u32) {
+        (proxy.id().interface().name.into(), proxy.version())
+    }
+
+    /// Interface names and versions of all bound globals, including seats
+    pub fn get_protocol_versions(&self) -> Vec<(String, u32)> {
+        let mut versions = self.bound_globals.clone();
+        versions.extend(
+            self.seat_state
+                .seats()
+                .map(|seat| Self::describe_global(&seat)),
+        );
+        versions
+    }
+
+    pub fn get_output_infos(&self) -> Vec<OutputInfo> {
+        self.output_state
+            .outputs()
+            .filter_map(|output| self.output_state.info(&output))
+            .collect()
+    }
+
     pub fn get_modifiers(&self) -> &Modifiers {
         &self.modifiers
     }
 
     fn set_cursor(&mut self) {
-        if let Some(ref mut pointer) = self.themed_pointer {
+        for pointer in &self.themed_pointers {
+            // fails for pointers that aren't over our surface
             if pointer
                 .set_cursor(&self.connection, self.requested_cursor)
                 .is_ok()
@@ -210,6 +478,53 @@ impl WaylandClient {
         }
     }
 
+    /// Restricts input and window activation to the seat with this name, or uses all seats
+    pub fn set_pinned_seat(&mut self, name: Option<String>) {
+        if self.pinned_seat != name {
+            self.input_seat = None;
+        }
+        self.pinned_seat = name;
+    }
+
+    fn get_seat_name(&self, seat: &WlSeat) -> Option<String> {
+        self.seat_state.info(seat).and_then(|info| info.name)
+    }
+
+    /// Whether input from this seat is handled, remembering it as the seat to activate windows on
+    fn accept_input_from(&mut self, seat: &WlSeat) -> bool {
+        if let Some(pinned_seat) = &self.pinned_seat
+            && self.get_seat_name(seat).as_ref() != Some(pinned_seat)
+        {
+            return false;
+        }
+        self.input_seat = Some(seat.clone());
+        true
+    }
+
+    fn get_keyboard_seat(keyboard: &WlKeyboard) -> Option<WlSeat> {
+        keyboard
+            .data::<KeyboardData<Self>>()
+            .map(|data| data.seat().clone())
+    }
+
+    fn accept_keyboard_input(&mut self, keyboard: &WlKeyboard) -> bool {
+        Self::get_keyboard_seat(keyboard).is_none_or(|seat| self.accept_input_from(&seat))
+    }
+
+    /// The seat windows are activated on, the pinned seat or the one last used with the switcher
+    fn get_activation_seat(&self) -> Option<WlSeat> {
+        match &self.pinned_seat {
+            Some(name) => self
+                .seat_state
+                .seats()
+                .find(|seat| self.get_seat_name(seat).as_ref() == Some(name)),
+            None => self
+                .input_seat
+                .clone()
+                .or_else(|| self.seat_state.seats().next()),
+        }
+    }
+
     pub fn request_cursor(&mut self, icon: CursorIcon) {
         self.requested_cursor = icon;
         if let Some(current_icon) = self.current_cursor
@@ -219,12 +534,45 @@ impl WaylandClient {
         }
     }
 
+    /// Shows the switcher on the given outputs, the first one receives keyboard input.
+    /// Without any outputs, the compositor picks one.
     pub fn create_surfaces(
         &mut self,
         queue_handle: &QueueHandle<Self>,
         width: u32,
         height: u32,
+        outputs: &[WlOutput],
     ) -> anyhow::Result<()> {
+        let (output, mirrored_outputs) = match outputs.split_first() {
+            Some((output, mirrored_outputs)) => (Some(output), mirrored_outputs),
+            None => (None, [].as_slice()),
+        };
+
+        if let Some(output) = output {
+            self.current_output = output.clone().into();
+        }
+
+        self.surfaces = self
+            .create_layer_surface(queue_handle, (width, height), output, true)
+            .into();
+        self.mirrored_surfaces = mirrored_outputs
+            .iter()
+            .map(|output| {
+                self.create_layer_surface(queue_handle, (width, height), Some(output), false)
+            })
+            .collect();
+        self.apply_surface_scale();
+
+        Ok(())
+    }
+
+    fn create_layer_surface(
+        &self,
+        queue_handle: &QueueHandle<Self>,
+        (width, height): (u32, u32),
+        output: Option<&WlOutput>,
+        main: bool,
+    ) -> Surfaces {
         let wl_surface = self.compositor_state.create_surface(queue_handle);
 
         let layer_surface = self.layer_shell.create_layer_surface(
@@ -232,62 +580,169 @@ impl WaylandClient {
             wl_surface.clone(),
             Layer::Overlay,
             Some(env!("CARGO_CRATE_NAME")),
-            None,
+            output,
         );
 
         // Anchor to top and horizontally centered
         layer_surface.set_anchor(Anchor::TOP | Anchor::LEFT | Anchor::RIGHT | Anchor::BOTTOM);
-        layer_surface.set_keyboard_interactivity(KeyboardInteractivity::Exclusive);
+        layer_surface.set_keyboard_interactivity(match main {
+            true => KeyboardInteractivity::Exclusive,
+            false => KeyboardInteractivity::None,
+        });
         layer_surface.set_exclusive_zone(-1); // Don't reserve space
         layer_surface.set_size(width, height);
         layer_surface.set_margin(0, 0, 0, 0);
+
+        // buffers are rendered at the scale the compositor prefers and scaled back down to our size
+        let (fractional_scale, viewport) = match &self.fractional_scale {
+            Some(fractional_scale) => {
+                let viewport =
+                    fractional_scale
+                        .viewporter
+                        .get_viewport(&wl_surface, queue_handle, ());
+                viewport.set_destination(width as i32, height as i32);
+
+                // all surfaces share one buffer, so only the main surface's scale is followed
+                let scale = main.then(|| {
+                    fractional_scale.manager.get_fractional_scale(
+                        &wl_surface,
+                        queue_handle,
+                        wl_surface.clone(),
+                    )
+                });
+                (scale, Some(viewport))
+            }
+            None => (None, None),
+        };
         layer_surface.commit();
 
-        let surfaces = Surfaces {
+        Surfaces {
             wl_surface,
             layer_surface,
-        };
+            fractional_scale,
+            viewport,
+            configured: false,
+        }
+    }
+
+    fn all_surfaces(&self) -> impl Iterator<Item = &Surfaces> {
+        self.surfaces.iter().chain(&self.mirrored_surfaces)
+    }
 
-        self.surfaces = Some(surfaces);
+    /// Surfaces that have been configured and can be rendered to
+    fn configured_surfaces(&self) -> impl Iterator<Item = &Surfaces> {
+        self.all_surfaces().filter(|surfaces| surfaces.configured)
+    }
 
-        Ok(())
+    fn is_main_surface(&self, surface: &WlSurface) -> bool {
+        self.surfaces
+            .as_ref()
+            .is_some_and(|surfaces| surfaces.wl_surface == *surface)
     }
 
     pub fn create_buffer(&mut self, width: i32, height: i32) -> anyhow::Result<Buffer> {
-        let (buffer, _) = self
-            .pool
-            .create_buffer(width, height, width * 4, Format::Argb8888)?;
-        Ok(buffer)
+        self.pool
+            .create_buffer(width, height, width * 4, Format::Argb8888)
     }
 
+    /// Fails if the buffer is in use by the compositor or belongs to a pool that has since
+    /// been trimmed
     pub fn get_buffer_mut<T>(
         &mut self,
         buffer: &Buffer,
         handle_buffer: impl FnOnce(&mut [u8]) -> T,
-    ) -> T {
-        let canvas = buffer.canvas(&mut self.pool).unwrap();
+    ) -> anyhow::Result<T> {
+        let canvas = self
+            .pool
+            .canvas(buffer)
+            .context("buffer can't be accessed from the shm pool")?;
+
+        Ok(handle_buffer(canvas))
+    }
+
+    /// Hands a captured buffer back once its pixels have been read, so it can be captured into again
+    pub fn recycle_buffer(&mut self, captured: CapturedBuffer) {
+        self.pool.recycle(captured);
+    }
+
+    /// Gives memory back to the system after the switcher was hidden.
+    /// Does nothing while the surface or any capture is still using the pool.
+    pub fn trim_pool(&mut self) -> anyhow::Result<()> {
+        let capturing = !self.screencopy_frames.is_empty()
+            || !self.hyprland_frames.is_empty()
+            || !self.toplevel_captures.is_empty();
+
+        if self.surfaces.is_some() || capturing {
+            return Ok(());
+        }
+
+        self.pool.trim(&self.shm)
+    }
 
-        handle_buffer(canvas)
+    pub fn get_pool_size(&self) -> usize {
+        self.pool.len()
     }
 
     pub fn update_surface_buffer(&mut self, buffer: &Buffer) {
-        let Some(Surfaces { wl_surface, .. }) = &mut self.surfaces else {
+        if self.surfaces.is_none() {
             tracing::warn!("No active surface");
             return;
         };
 
-        wl_surface.attach(Some(buffer.wl_buffer()), 0, 0);
-        wl_surface.damage_buffer(0, 0, buffer.stride() / 4, buffer.height());
-        wl_surface.commit();
+        for Surfaces { wl_surface, .. } in self.configured_surfaces() {
+            wl_surface.attach(Some(buffer.wl_buffer()), 0, 0);
+            wl_surface.damage_buffer(0, 0, buffer.stride() / 4, buffer.height());
+            wl_surface.commit();
+        }
     }
 
     pub fn has_surfaces(&self) -> bool {
         self.surfaces.is_some()
     }
 
+    /// Asks the compositor for a new size, the surfaces are resized once it is configured
+    pub fn resize_surfaces(&self, width: u32, height: u32) {
+        for surfaces in self.all_surfaces() {
+            surfaces.layer_surface.set_size(width, height);
+            surfaces.layer_surface.commit();
+        }
+    }
+
     pub fn destroy_surfaces(&mut self) {
         self.current_cursor = None;
-        self.surfaces.take();
+        if let Some(surfaces) = self.surfaces.take() {
+            surfaces.destroy();
+        }
+        for surfaces in self.mirrored_surfaces.drain(..) {
+            surfaces.destroy();
+        }
+    }
+
+    /// Buffer pixels per logical pixel of the switcher surface
+    pub fn get_surface_scale(&self) -> f64 {
+        self.surface_scale
+    }
+
+    /// Sets the integer buffer scale on the surface, which must happen before
+    /// attaching buffers of the new size. Fractional scales only need the viewport.
+    pub fn apply_surface_scale(&self) {
+        for surfaces in self.all_surfaces() {
+            if surfaces.viewport.is_none() {
+                surfaces
+                    .wl_surface
+                    .set_buffer_scale(self.surface_scale.round() as i32);
+            }
+        }
+    }
+
+    fn set_surface_scale(&mut self, scale: f64) {
+        if self.surface_scale == scale {
+            return;
+        }
+
+        tracing::debug!("Surface scale changed to {}", scale);
+        self.surface_scale = scale;
+        self.send_event(WaylandClientEvent::ScaleChanged(scale));
     }
 
     pub fn request_paint(&mut self, qh: &QueueHandle<Self>) {
@@ -297,10 +752,20 @@ impl WaylandClient {
         }
     }
 
-    pub fn get_raw_handles(&self) -> anyhow::Result<RawHandles> {
-        let surfaces = self.surfaces.as_ref().context("surfaces is None")?;
+    /// Handles for every configured surface showing the switcher, starting with the main one
+    pub fn get_raw_handles(&self) -> anyhow::Result<Vec<RawHandles>> {
+        if self.surfaces.is_none() {
+            anyhow::bail!("surfaces is None");
+        }
+
+        self.configured_surfaces()
+            .map(|surfaces| self.get_surface_raw_handles(&surfaces.wl_surface))
+            .collect()
+    }
+
+    fn get_surface_raw_handles(&self, wl_surface: &WlSurface) -> anyhow::Result<RawHandles> {
         let display_ptr = self.connection.backend().display_ptr() as *mut c_void;
-        let surface_ptr = surfaces.wl_surface.id().as_ptr() as *mut c_void;
+        let surface_ptr = wl_surface.id().as_ptr() as *mut c_void;
 
         let raw_display_handle = {
             let display = NonNull::new(display_ptr).context("display_ptr is null")?;
@@ -320,21 +785,178 @@ impl WaylandClient {
         })
     }
 
+    pub fn get_outputs(&self) -> Vec<WlOutput> {
+        self.output_state.outputs().collect()
+    }
+
+    pub fn find_output_by_name(&self, name: &str) -> Option<WlOutput> {
+        self.output_state.outputs().find(|output| {
+            self.output_state
+                .info(output)
+                .is_some_and(|info| info.name.as_deref() == Some(name))
+        })
+    }
+
+    /// The output containing a point in the global logical coordinate space
+    pub fn find_output_at(&self, (x, y): (i32, i32)) -> Option<WlOutput> {
+        self.output_state.outputs().find(|output| {
+            let Some(OutputInfo {
+                logical_position: Some((output_x, output_y)),
+                logical_size: Some((width, height)),
+                ..
+            }) = self.output_state.info(output)
+            else {
+                return false;
+            };
+
+            (output_x..output_x + width).contains(&x) && (output_y..output_y + height).contains(&y)
+        })
+    }
+
+    /// The first output a window has been shown on, if the compositor reports it
+    pub fn get_window_output(&self, id: u32) -> Option<WlOutput> {
+        self.toplevel_windows
+            .iter()
+            .find(|window| window.handle.id().protocol_id() == id)
+            .and_then(|window| window.outputs.first().cloned())
+    }
+
     pub fn activate_window(&mut self, id: u32) {
-        let Some(window_handle) = self
+        let Some(window) = self
             .toplevel_windows
             .iter()
-            .find(|window| window.id().protocol_id() == id)
+            .find(|window| window.handle.id().protocol_id() == id)
         else {
             return;
         };
+        let window_handle = &window.handle;
 
-        let seat_count = self.seat_state.seats().count();
-        tracing::debug!("activating window {}, seat count {}", id, seat_count);
+        // activating a minimized window doesn't necessarily restore it
+        if window.state.minimized {
+            window_handle.unset_minimized();
+        }
 
-        if let Some(seat) = &self.seat_state.seats().next() {
-            window_handle.activate(seat);
+        let Some(seat) = self.get_activation_seat() else {
+            tracing::warn!("Could not activate window {}, no seat found", id);
+            return;
         };
+        tracing::debug!(
+            "activating window {} on seat {:?}",
+            id,
+            self.get_seat_name(&seat)
+        );
+        // An xdg-activation token can't help here: zwlr_foreign_toplevel_handle_v1.activate
+        // only takes a seat, and xdg_activation_v1.activate only works on surfaces of the
+        // client that holds the token, so there is no way to hand one to another client's
+        // window. Compositors treat this request as coming from a privileged client instead.
+        window_handle.activate(&seat);
+    }
+
+    pub fn can_capture_toplevels(&self) -> bool {
+        self.hyprland_toplevel_export.is_some() || self.toplevel_capture.is_some()
+    }
+
+    /// Finds the ext-foreign-toplevel-list handle for a wlr toplevel. The two protocols don't
+    /// share identifiers, so toplevels are paired up by app id and title. Windows that share
+    /// both with another window can't be told apart and aren't paired at all, otherwise they
+    /// could end up with each other's previews.
+    fn find_ext_toplevel(&self, id: u32) -> Option<ExtForeignToplevelHandleV1> {
+        let window = self
+            .toplevel_windows
+            .iter()
+            .find(|window| window.handle.id().protocol_id() == id)?;
+        let is_same = |title: &str, app_id: &str| title == window.title && app_id == window.app_id;
+
+        let window_count = self
+            .toplevel_windows
+            .iter()
+            .filter(|other| is_same(&other.title, &other.app_id))
+            .count();
+        if window_count != 1 {
+            return None;
+        }
+
+        let mut handles = self.ext_toplevel_list.toplevels().iter().filter(|handle| {
+            self.ext_toplevel_list
+                .info(handle)
+                .is_some_and(|info| is_same(&info.title, &info.app_id))
+        });
+        match (handles.next(), handles.next()) {
+            (Some(handle), None) => Some(handle.clone()),
+            _ => None,
+        }
+    }
+
+    /// Captures the contents of a toplevel, even if it is hidden or covered by other windows
+    pub fn capture_toplevel(
+        &mut self,
+        id: u32,
+        queue_handle: &QueueHandle<Self>,
+    ) -> anyhow::Result<()> {
+        if let Some(manager) = &self.hyprland_toplevel_export {
+            let handle = self
+                .toplevel_windows
+                .iter()
+                .find(|window| window.handle.id().protocol_id() == id)
+                .map(|window| &window.handle)
+                .context("unknown window")?;
+
+            if self.hyprland_frames.values().all(|state| state.id != id) {
+                let frame =
+                    manager.capture_toplevel_with_wlr_toplevel_handle(0, handle, queue_handle, ());
+                self.hyprland_frames
+                    .insert(frame, ScreencopyFrameState::new(id));
+            }
+
+            return Ok(());
+        }
+
+        let capture = self
+            .toplevel_capture
+            .as_ref()
+            .context("compositor does not support capturing toplevels")?;
+
+        if self
+            .toplevel_captures
+            .values()
+            .any(|state| state.frame_state.id == id)
+        {
+            return Ok(());
+        }
+
+        let handle = self
+            .find_ext_toplevel(id)
+            .context("no unique matching ext-foreign-toplevel-list handle")?;
+
+        let source = capture
+            .source_manager
+            .create_source(&handle, queue_handle, ());
+        let session = capture.copy_manager.create_session(
+            &source,
+            ext_image_copy_capture_manager_v1::Options::empty(),
+            queue_handle,
+            (),
+        );
+
+        self.toplevel_captures.insert(
+            session,
+            ToplevelCaptureState {
+                source,
+                frame_state: ScreencopyFrameState::new(id),
+            },
+        );
+
+        Ok(())
+    }
+
+    fn finish_toplevel_capture(
+        &mut self,
+        session: &ExtImageCopyCaptureSessionV1,
+    ) -> Option<ScreencopyFrameState> {
+        let state = self.toplevel_captures.remove(session)?;
+        session.destroy();
+        state.source.destroy();
+        Some(state.frame_state)
     }
 
     pub fn capture_window_region(
@@ -350,49 +972,133 @@ impl WaylandClient {
             return Ok(());
         }
 
-        // I think this function can be much nicer if we get window preview via
-        // ext_foreign_toplevel_image_capture_source_manager_v1
+        // this is the fallback for compositors that can't capture toplevels directly, see
+        // capture_toplevel
+        if self.region_captures.contains_key(&id) {
+            return Ok(());
+        }
+
+        let generation = self.next_region_generation;
+        self.next_region_generation += 1;
+
+        // capture the part of the window on every output it intersects with, the pieces are
+        // stitched back together once all of them are ready
+        let regions: Vec<_> = self
+            .output_state
+            .outputs()
+            .filter_map(|output| {
+                let info = self.output_state.info(&output)?;
+                let (output_x, output_y) = info.logical_position.unwrap_or(info.location);
+                let (output_w, output_h) = info.logical_size.unwrap_or_default();
+
+                let left = x.max(output_x);
+                let top = y.max(output_y);
+                let right = (x + width).min(output_x + output_w);
+                let bottom = (y + height).min(output_y + output_h);
+
+                if left >= right || top >= bottom {
+                    return None;
+                }
 
-        // TODO: stitch windows that are part of different monitors
+                let placement = RegionPlacement {
+                    region: (left - x, top - y, right - left, bottom - top),
+                    transform: info.transform,
+                    generation,
+                };
+                (output, (left - output_x, top - output_y), placement).into()
+            })
+            .collect();
+
+        if regions.is_empty() {
+            debug!(
+                "no output contains region ({}, {}) {}x{}",
+                x, y, width, height
+            );
+            return Ok(());
+        }
 
-        // Find the output that contains the given coordinates based on logical_position
-        let output_with_coords = self.output_state.outputs().find_map(|output| {
-            let Some(info) = self.output_state.info(&output) else {
-                return None;
-            };
-            let (output_x, output_y) = info.logical_position.unwrap_or(info.location);
-            let (output_w, output_h) = info.logical_size.unwrap_or_default();
+        self.region_captures.insert(
+            id,
+            RegionCapture {
+                generation,
+                size: (width, height),
+                expected_pieces: regions.len(),
+                pieces: Vec::new(),
+            },
+        );
 
-            let relative_x = x - output_x;
-            let relative_y = y - output_y;
+        for (output, (relative_x, relative_y), placement) in regions {
+            let (_, _, region_width, region_height) = placement.region;
+            let frame = self.screencopy_manager.capture_output_region(
+                0,
+                &output,
+                relative_x,
+                relative_y,
+                region_width,
+                region_height,
+                queue_handle,
+                (),
+            );
+
+            self.screencopy_frames.insert(
+                frame,
+                ScreencopyFrameState {
+                    placement: placement.into(),
+                    ..ScreencopyFrameState::new(id)
+                },
+            );
+        }
 
-            if (0..output_w).contains(&relative_x) && (0..output_h).contains(&relative_y) {
-                (output, relative_x, relative_y).into()
-            } else {
-                None
-            }
-        });
+        Ok(())
+    }
 
-        let Some((output, relative_x, relative_y)) = output_with_coords else {
-            debug!("no output contains coordinates ({}, {})", x, y);
-            return Ok(());
+    /// Forgets a screencopy frame that won't be captured. Without this piece the window can't
+    /// be stitched together, so its region capture is abandoned as well.
+    fn abandon_screencopy_frame(&mut self, frame: &ZwlrScreencopyFrameV1) {
+        if let Some(frame_state) = self.screencopy_frames.remove(frame)
+            && let Some(placement) = &frame_state.placement
+            && self
+                .region_captures
+                .get(&frame_state.id)
+                .is_some_and(|capture| capture.generation == placement.generation)
+        {
+            self.region_captures.remove(&frame_state.id);
+        }
+        frame.destroy();
+    }
+
+    /// Adds a captured region to its window, sending the window once all regions are captured
+    fn finish_region_piece(&mut self, mut frame_state: ScreencopyFrameState) {
+        let Some(placement) = frame_state.placement.take() else {
+            return;
+        };
+        let Some((id, buffer)) = frame_state.into_captured() else {
+            return;
         };
 
-        let frame = self.screencopy_manager.capture_output_region(
-            0,
-            &output,
-            relative_x,
-            relative_y,
-            width,
-            height,
-            queue_handle,
-            (),
-        );
+        let Some(capture) = self
+            .region_captures
+            .get_mut(&id)
+            .filter(|capture| capture.generation == placement.generation)
+        else {
+            // the capture this piece belongs to failed and was abandoned
+            self.pool.recycle(buffer);
+            return;
+        };
 
-        self.screencopy_frames
-            .insert(frame, ScreencopyFrameState::new(id));
+        capture.pieces.push(CapturePiece {
+            buffer,
+            region: placement.region,
+            transform: placement.transform,
+        });
 
-        Ok(())
+        if capture.pieces.len() < capture.expected_pieces {
+            return;
+        }
+
+        if let Some(RegionCapture { size, pieces, .. }) = self.region_captures.remove(&id) {
+            self.send_event(WaylandClientEvent::ScreencopyRegionsDone(id, size, pieces));
+        }
     }
 
     pub fn get_monitor_width(&self) -> Option<u32> {
@@ -420,9 +1126,15 @@ impl CompositorHandler for WaylandClient {
         &mut self,
         _: &Connection,
         _: &QueueHandle<Self>,
-        _: &WlSurface,
-        _: i32,
+        surface: &WlSurface,
+        new_factor: i32,
     ) {
+        // the fractional scale takes precedence when it's available
+        if self.fractional_scale.is_some() || !self.is_main_surface(surface) {
+            return;
+        }
+
+        self.set_surface_scale(new_factor as f64);
     }
 
     fn transform_changed(
@@ -435,17 +1147,19 @@ impl CompositorHandler for WaylandClient {
     }
 
     fn frame(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlSurface, _: u32) {
-        self.wl_tx.send(WaylandClientEvent::PaintRequest).unwrap();
+        self.send_event(WaylandClientEvent::PaintRequest);
     }
 
     fn surface_enter(
         &mut self,
         _: &Connection,
         _: &QueueHandle<Self>,
-        _: &WlSurface,
+        surface: &WlSurface,
         output: &WlOutput,
     ) {
-        self.current_output = output.clone().into();
+        if self.is_main_surface(surface) {
+            self.current_output = output.clone().into();
+        }
     }
 
     fn surface_leave(
@@ -479,15 +1193,43 @@ impl LayerShellHandler for WaylandClient {
         &mut self,
         _: &Connection,
         _: &QueueHandle<Self>,
-        _: &LayerSurface,
+        layer_surface: &LayerSurface,
         layer_surface_configure: LayerSurfaceConfigure,
         _: u32,
     ) {
-        self.wl_tx
-            .send(WaylandClientEvent::LayerShellConfigure(
-                layer_surface_configure,
-            ))
-            .unwrap();
+        let (width, height) = layer_surface_configure.new_size;
+        if width > 0 && height > 0 {
+            for viewport in self
+                .all_surfaces()
+                .filter_map(|surfaces| surfaces.viewport.as_ref())
+            {
+                viewport.set_destination(width as i32, height as i32);
+            }
+        }
+
+        let wl_surface = layer_surface.wl_surface();
+        if let Some(surfaces) = self
+            .surfaces
+            .iter_mut()
+            .chain(&mut self.mirrored_surfaces)
+            .find(|surfaces| surfaces.wl_surface == *wl_surface)
+        {
+            surfaces.configured = true;
+        }
+
+        // mirrored surfaces configured after the main one are added to the renderer then,
+        // earlier ones are picked up with the main surface
+        if !self
+            .surfaces
+            .as_ref()
+            .is_some_and(|surfaces| surfaces.configured)
+        {
+            return;
+        }
+
+        self.send_event(WaylandClientEvent::LayerShellConfigure(
+            layer_surface_configure,
+        ));
     }
 }
 
@@ -519,7 +1261,7 @@ impl SeatHandler for WaylandClient {
             tracing::warn!("Failed to get keyboard capability");
         }
 
-        if capability == Capability::Pointer && self.themed_pointer.is_none() {
+        if capability == Capability::Pointer {
             let surface = self.compositor_state.create_surface(qh);
             match self.seat_state.get_pointer_with_theme(
                 qh,
@@ -528,7 +1270,7 @@ impl SeatHandler for WaylandClient {
                 surface,
                 ThemeSpec::default(),
             ) {
-                Ok(pointer) => self.themed_pointer = Some(pointer),
+                Ok(pointer) => self.themed_pointers.push(pointer),
                 Err(e) => tracing::warn!("Failed to get themed pointer: {:?}", e),
             }
         }
@@ -538,12 +1280,24 @@ impl SeatHandler for WaylandClient {
         &mut self,
         _: &Connection,
         _: &QueueHandle<Self>,
-        _: WlSeat,
-        _: Capability,
-    ) {
+        seat: WlSeat,
+        capability: Capability,
+    ) {
+        if capability == Capability::Pointer {
+            self.themed_pointers.retain(|pointer| {
+                pointer
+                    .pointer()
+                    .data::<PointerData>()
+                    .is_none_or(|data| *data.seat() != seat)
+            });
+        }
     }
 
-    fn remove_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, _: WlSeat) {}
+    fn remove_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, seat: WlSeat) {
+        if self.input_seat.as_ref() == Some(&seat) {
+            self.input_seat = None;
+        }
+    }
 }
 
 impl KeyboardHandler for WaylandClient {
@@ -551,12 +1305,15 @@ impl KeyboardHandler for WaylandClient {
         &mut self,
         _: &Connection,
         _: &QueueHandle<Self>,
-        _: &WlKeyboard,
+        keyboard: &WlKeyboard,
         _: &WlSurface,
         _: u32,
         _: &[u32],
         _: &[Keysym],
     ) {
+        if let Some(seat) = Self::get_keyboard_seat(keyboard) {
+            self.accept_input_from(&seat);
+        }
     }
 
     fn leave(
@@ -573,13 +1330,16 @@ impl KeyboardHandler for WaylandClient {
         &mut self,
         _: &Connection,
         _: &QueueHandle<Self>,
-        _: &WlKeyboard,
+        keyboard: &WlKeyboard,
         _: u32,
         event: KeyEvent,
     ) {
+        if !self.accept_keyboard_input(keyboard) {
+            return;
+        }
         if let Ok(event) = WaylandClientEvent::from_wl_key_event(event, true, false, self.modifiers)
         {
-            self.wl_tx.send(event).unwrap()
+            self.send_event(event)
         }
     }
 
@@ -587,14 +1347,17 @@ impl KeyboardHandler for WaylandClient {
         &mut self,
         _: &Connection,
         _: &QueueHandle<Self>,
-        _: &WlKeyboard,
+        keyboard: &WlKeyboard,
         _: u32,
         event: KeyEvent,
     ) {
+        if !self.accept_keyboard_input(keyboard) {
+            return;
+        }
         if let Ok(event) =
             WaylandClientEvent::from_wl_key_event(event, false, false, self.modifiers)
         {
-            self.wl_tx.send(event).unwrap()
+            self.send_event(event)
         }
     }
 
@@ -602,27 +1365,33 @@ impl KeyboardHandler for WaylandClient {
         &mut self,
         _: &Connection,
         _: &QueueHandle<Self>,
-        _: &WlKeyboard,
+        keyboard: &WlKeyboard,
         _: u32,
         modifiers: Modifiers,
         _: smithay_client_toolkit::seat::keyboard::RawModifiers,
         _: u32,
     ) {
+        if !self.accept_keyboard_input(keyboard) {
+            return;
+        }
         self.modifiers = modifiers;
-        self.wl_tx.send(WaylandClientEvent::ModifierChange).unwrap();
+        self.send_event(WaylandClientEvent::ModifierChange);
     }
 
     fn repeat_key(
         &mut self,
         _: &Connection,
         _: &QueueHandle<Self>,
-        _: &WlKeyboard,
+        keyboard: &WlKeyboard,
         _: u32,
         event: KeyEvent,
     ) {
+        if !self.accept_keyboard_input(keyboard) {
+            return;
+        }
         if let Ok(event) = WaylandClientEvent::from_wl_key_event(event, true, true, self.modifiers)
         {
-            self.wl_tx.send(event).unwrap()
+            self.send_event(event)
         }
     }
 }
@@ -632,9 +1401,15 @@ impl PointerHandler for WaylandClient {
         &mut self,
         _: &Connection,
         _: &QueueHandle<Self>,
-        _: &WlPointer,
+        pointer: &WlPointer,
         events: &[PointerEvent],
     ) {
+        if let Some(data) = pointer.data::<PointerData>()
+            && !self.accept_input_from(data.seat())
+        {
+            return;
+        }
+
         // Set cursor on enter events
         for event in events {
             if matches!(event.kind, PointerEventKind::Enter { .. }) {
@@ -644,7 +1419,7 @@ impl PointerHandler for WaylandClient {
         }
 
         if let Ok(event) = WaylandClientEvent::from_wl_pointer_events(events, self.modifiers) {
-            self.wl_tx.send(event).unwrap()
+            self.send_event(event)
         }
     }
 }
@@ -664,6 +1439,62 @@ delegate_pointer!(WaylandClient);
 delegate_registry!(WaylandClient);
 delegate_shm!(WaylandClient);
 
+impl Dispatch<WpFractionalScaleManagerV1, ()> for WaylandClient {
+    fn event(
+        _: &mut Self,
+        _: &WpFractionalScaleManagerV1,
+        _: <WpFractionalScaleManagerV1 as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WpFractionalScaleV1, WlSurface> for WaylandClient {
+    fn event(
+        state: &mut Self,
+        _: &WpFractionalScaleV1,
+        event: <WpFractionalScaleV1 as Proxy>::Event,
+        surface: &WlSurface,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if !state.is_main_surface(surface) {
+            return;
+        }
+
+        if let wp_fractional_scale_v1::Event::PreferredScale { scale } = event {
+            // the scale is sent as a fraction with a denominator of 120
+            state.set_surface_scale(scale as f64 / 120.0);
+        }
+    }
+}
+
+impl Dispatch<WpViewporter, ()> for WaylandClient {
+    fn event(
+        _: &mut Self,
+        _: &WpViewporter,
+        _: <WpViewporter as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WpViewport, ()> for WaylandClient {
+    fn event(
+        _: &mut Self,
+        _: &WpViewport,
+        _: <WpViewport as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
 // Screencopy manager implementation
 impl Dispatch<ZwlrScreencopyManagerV1, ()> for WaylandClient {
     fn event(
@@ -710,72 +1541,38 @@ impl Dispatch<ZwlrScreencopyFrameV1, ()> for WaylandClient {
 
                 let WEnum::Value(format) = format else { return };
 
-                match format {
-                    Format::Argb8888 | Format::Xrgb8888 | Format::Bgr888 => (),
-                    _ => return,
-                };
-
-                let _ = frame_state.set_buffer_details(width, height, stride, format);
-            }
-            Event::BufferDone => {
-                let Some((width, height, stride, format)) = frame_state.get_buffer_details() else {
-                    state.screencopy_frames.remove(frame);
+                if !pixel_format::is_supported(format) {
                     return;
-                };
-
-                tracing::debug!("widthxheight (stride) {}x{} ({})", width, height, stride);
-
-                let Ok((buffer, _)) = state.pool.create_buffer(width, height, stride, format)
-                else {
-                    tracing::error!("could not create buffer from pool!");
-                    state.screencopy_frames.remove(frame);
-                    return;
-                };
+                }
 
-                frame.copy(buffer.wl_buffer());
-                frame_state.buffer = buffer.into();
+                let _ = frame_state.set_buffer_details(width, height, stride, format);
             }
+            Event::BufferDone => match frame_state.create_buffer(&mut state.pool) {
+                Ok(buffer) => frame.copy(buffer.wl_buffer()),
+                Err(err) => {
+                    tracing::error!("{}", err);
+                    state.abandon_screencopy_frame(frame);
+                }
+            },
             Event::Flags { flags } => {
                 use zwlr_screencopy_frame_v1::Flags;
 
                 match flags {
                     WEnum::Value(flags) => {
-                        if flags.contains(Flags::YInvert) {
-                            warn!("TODO: Handle screencopy YInvert");
-                        }
+                        frame_state.y_invert = flags.contains(Flags::YInvert);
                     }
                     WEnum::Unknown(flag) => warn!("Unknown screencopy flag: {}", flag),
                 };
             }
             Event::Ready { .. } => {
-                tracing::debug!(
-                    "ready buffer {:?}",
-                    &frame_state
-                        .buffer
-                        .as_ref()
-                        .unwrap()
-                        .canvas(&mut state.pool)
-                        .context("missing")
-                        .unwrap()[0..4]
-                );
-
-                if let Some(ScreencopyFrameState {
-                    id,
-                    buffer: Some(buffer),
-                    format: Some(format),
-                    ..
-                }) = state.screencopy_frames.remove(frame)
-                {
-                    state
-                        .wl_tx
-                        .send(WaylandClientEvent::ScreencopyDone(id, buffer, format))
-                        .unwrap();
+                if let Some(frame_state) = state.screencopy_frames.remove(frame) {
+                    state.finish_region_piece(frame_state);
                 }
                 frame.destroy();
             }
             Event::Failed => {
                 tracing::warn!("Screencopy failed");
-                state.screencopy_frames.remove(frame);
+                state.abandon_screencopy_frame(frame);
             }
 
             // currently unused because we never call frame.copy_with_damage
@@ -783,7 +1580,7 @@ impl Dispatch<ZwlrScreencopyFrameV1, ()> for WaylandClient {
 
             // LinuxDmabuf is a possible perf enhancement that can be explored in the future
             Event::LinuxDmabuf { .. } => {}
-            _ => unimplemented!(),
+            _ => tracing::warn!("Unknown screencopy frame event"),
         }
     }
 }
@@ -802,18 +1599,29 @@ impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for WaylandClient {
 
         match event {
             Event::Toplevel { toplevel } => {
-                state
-                    .wl_tx
-                    .send(WaylandClientEvent::TopLevelAdded(
-                        toplevel.id().protocol_id(),
-                    ))
-                    .unwrap();
-                state.toplevel_windows.push(toplevel);
+                state.send_event(WaylandClientEvent::TopLevelAdded(
+                    toplevel.id().protocol_id(),
+                ));
+                state.toplevel_windows.push(Toplevel {
+                    handle: toplevel,
+                    title: String::new(),
+                    app_id: String::new(),
+                    outputs: Vec::new(),
+                    state: ToplevelState::default(),
+                });
             }
+            // the compositor won't tell us about windows anymore, usually because it is
+            // shutting down
             Event::Finished => {
-                state.toplevel_windows.clear();
+                tracing::warn!("The compositor stopped sending window updates");
+                for window in mem::take(&mut state.toplevel_windows) {
+                    state.send_event(WaylandClientEvent::TopLevelRemoved(
+                        window.handle.id().protocol_id(),
+                    ));
+                    window.handle.destroy();
+                }
             }
-            _ => unimplemented!(),
+            _ => tracing::warn!("Unknown foreign toplevel manager event"),
         }
     }
 
@@ -836,38 +1644,368 @@ impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for WaylandClient {
 
         let id = handle.id().protocol_id();
 
+        let window = state
+            .toplevel_windows
+            .iter_mut()
+            .find(|window| window.handle == *handle);
+
         let client_event = match event {
-            Event::Title { title } => WaylandClientEvent::TopLevelTitleUpdate(id, title).into(),
-            Event::AppId { app_id } => WaylandClientEvent::TopLevelAppIdUpdate(id, app_id).into(),
-            Event::Closed => WaylandClientEvent::TopLevelRemoved(id).into(),
+            Event::Title { title } => {
+                if let Some(window) = window {
+                    window.title = title.clone();
+                }
+                WaylandClientEvent::TopLevelTitleUpdate(id, title).into()
+            }
+            Event::AppId { app_id } => {
+                if let Some(window) = window {
+                    window.app_id = app_id.clone();
+                }
+                WaylandClientEvent::TopLevelAppIdUpdate(id, app_id).into()
+            }
+            Event::Closed => {
+                state
+                    .toplevel_windows
+                    .retain(|window| window.handle != *handle);
+                handle.destroy();
+                WaylandClientEvent::TopLevelRemoved(id).into()
+            }
             Event::State {
                 state: window_state,
             } => {
-                let activated = zwlr_foreign_toplevel_handle_v1::State::Activated as u8;
-
-                if window_state.contains(&activated) {
-                    WaylandClientEvent::TopLevelActivated(id).into()
-                } else {
-                    None
+                use zwlr_foreign_toplevel_handle_v1::State;
+
+                // the states are sent as an array of native endian u32s
+                let states: Vec<u32> = window_state
+                    .chunks_exact(4)
+                    .map(|bytes| u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                    .collect();
+                let has_state = |expected: State| states.contains(&(expected as u32));
+
+                let toplevel_state = ToplevelState {
+                    minimized: has_state(State::Minimized),
+                    maximized: has_state(State::Maximized),
+                    fullscreen: has_state(State::Fullscreen),
+                };
+                if let Some(window) = window {
+                    window.state = toplevel_state;
                 }
+                state.send_event(WaylandClientEvent::TopLevelStateUpdate(id, toplevel_state));
+
+                has_state(State::Activated).then_some(WaylandClientEvent::TopLevelActivated(id))
             }
 
             // this set of events have all been processed
             Event::Done => None,
 
             // windows have changed monitors
-            Event::OutputEnter { .. } => None,
-            Event::OutputLeave { .. } => None,
+            Event::OutputEnter { output } => {
+                if let Some(window) = window
+                    && !window.outputs.contains(&output)
+                {
+                    window.outputs.push(output);
+                }
+                None
+            }
+            Event::OutputLeave { output } => {
+                if let Some(window) = window {
+                    window.outputs.retain(|entered| *entered != output);
+                }
+                None
+            }
 
-            // when the parent of the toplevel changes(?)
-            Event::Parent { .. } => None,
-            _ => unimplemented!(),
+            // dialogs and other child windows
+            Event::Parent { parent } => WaylandClientEvent::TopLevelParentUpdate(
+                id,
+                parent.map(|parent| parent.id().protocol_id()),
+            )
+            .into(),
+            _ => {
+                tracing::warn!("Unknown foreign toplevel handle event");
+                None
+            }
         };
 
         let Some(client_event) = client_event else {
             return;
         };
 
-        state.wl_tx.send(client_event).unwrap();
+        state.send_event(client_event);
+    }
+}
+
+impl ForeignToplevelListHandler for WaylandClient {
+    fn foreign_toplevel_list_state(&mut self) -> &mut ForeignToplevelList {
+        &mut self.ext_toplevel_list
+    }
+
+    // the wlr foreign toplevel manager is used to track windows, ext handles are looked up
+    // when capturing
+    fn new_toplevel(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: ExtForeignToplevelHandleV1,
+    ) {
+    }
+
+    fn update_toplevel(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: ExtForeignToplevelHandleV1,
+    ) {
+    }
+
+    fn toplevel_closed(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: ExtForeignToplevelHandleV1,
+    ) {
+    }
+}
+
+delegate_foreign_toplevel_list!(WaylandClient);
+
+impl Dispatch<ExtForeignToplevelImageCaptureSourceManagerV1, ()> for WaylandClient {
+    fn event(
+        _: &mut Self,
+        _: &ExtForeignToplevelImageCaptureSourceManagerV1,
+        _: <ExtForeignToplevelImageCaptureSourceManagerV1 as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ExtImageCaptureSourceV1, ()> for WaylandClient {
+    fn event(
+        _: &mut Self,
+        _: &ExtImageCaptureSourceV1,
+        _: <ExtImageCaptureSourceV1 as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ExtImageCopyCaptureManagerV1, ()> for WaylandClient {
+    fn event(
+        _: &mut Self,
+        _: &ExtImageCopyCaptureManagerV1,
+        _: <ExtImageCopyCaptureManagerV1 as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+// Image copy capture session implementation
+impl Dispatch<ExtImageCopyCaptureSessionV1, ()> for WaylandClient {
+    fn event(
+        state: &mut Self,
+        session: &ExtImageCopyCaptureSessionV1,
+        event: ext_image_copy_capture_session_v1::Event,
+        _: &(),
+        _: &Connection,
+        queue_handle: &QueueHandle<Self>,
+    ) {
+        use ext_image_copy_capture_session_v1::Event;
+
+        let Some(ToplevelCaptureState { frame_state, .. }) =
+            state.toplevel_captures.get_mut(session)
+        else {
+            return;
+        };
+
+        match event {
+            Event::BufferSize { width, height } => {
+                tracing::debug!("Toplevel capture buffer size: {}x{}", width, height);
+
+                let (Ok(width), Ok(height)) = (width.try_into(), height.try_into()) else {
+                    return;
+                };
+                frame_state.width = width;
+                frame_state.height = height;
+            }
+            Event::ShmFormat { format } => {
+                let WEnum::Value(format) = format else { return };
+
+                if frame_state.format.is_none() && pixel_format::is_supported(format) {
+                    frame_state.format = format.into();
+                }
+            }
+            Event::Done => {
+                // constraints may be sent again, but we only ever capture a single frame
+                if frame_state.buffer.is_some() {
+                    return;
+                }
+
+                // we pick the stride ourselves, so there's no padding
+                let (width, height) = (frame_state.width, frame_state.height);
+                if let Some(bytes_per_pixel) =
+                    frame_state.format.and_then(pixel_format::bytes_per_pixel)
+                {
+                    frame_state.stride = width * bytes_per_pixel as i32;
+                }
+
+                let buffer = match frame_state.create_buffer(&mut state.pool) {
+                    Ok(buffer) => buffer,
+                    Err(err) => {
+                        tracing::warn!("Toplevel capture: {}", err);
+                        state.finish_toplevel_capture(session);
+                        return;
+                    }
+                };
+
+                let frame = session.create_frame(queue_handle, session.clone());
+                frame.attach_buffer(buffer.wl_buffer());
+                frame.damage_buffer(0, 0, width, height);
+                frame.capture();
+            }
+            Event::Stopped => {
+                tracing::debug!("Toplevel capture session stopped");
+                state.finish_toplevel_capture(session);
+            }
+
+            // LinuxDmabuf is a possible perf enhancement that can be explored in the future
+            Event::DmabufDevice { .. } | Event::DmabufFormat { .. } => {}
+            _ => tracing::warn!("Unknown toplevel capture session event"),
+        }
+    }
+}
+
+// Image copy capture frame implementation, the user data is the session the frame belongs to
+impl Dispatch<ExtImageCopyCaptureFrameV1, ExtImageCopyCaptureSessionV1> for WaylandClient {
+    fn event(
+        state: &mut Self,
+        frame: &ExtImageCopyCaptureFrameV1,
+        event: ext_image_copy_capture_frame_v1::Event,
+        session: &ExtImageCopyCaptureSessionV1,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        use ext_image_copy_capture_frame_v1::Event;
+
+        match event {
+            Event::Ready => {
+                frame.destroy();
+
+                if let Some(event) = state
+                    .finish_toplevel_capture(session)
+                    .and_then(ScreencopyFrameState::into_done_event)
+                {
+                    state.send_event(event);
+                }
+            }
+            Event::Failed { reason } => {
+                tracing::warn!("Toplevel capture failed: {:?}", reason);
+                frame.destroy();
+                state.finish_toplevel_capture(session);
+            }
+
+            // the whole frame is copied every time, so damage isn't interesting
+            Event::Damage { .. } | Event::PresentationTime { .. } => {}
+            Event::Transform { .. } => {}
+            _ => tracing::warn!("Unknown toplevel capture frame event"),
+        }
+    }
+}
+
+impl Dispatch<HyprlandToplevelExportManagerV1, ()> for WaylandClient {
+    fn event(
+        _: &mut Self,
+        _: &HyprlandToplevelExportManagerV1,
+        _: <HyprlandToplevelExportManagerV1 as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+// Hyprland toplevel export frame implementation, works the same as a screencopy frame
+impl Dispatch<HyprlandToplevelExportFrameV1, ()> for WaylandClient {
+    fn event(
+        state: &mut Self,
+        frame: &HyprlandToplevelExportFrameV1,
+        event: hyprland_toplevel_export_frame_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        use hyprland_toplevel_export_frame_v1::Event;
+
+        let Some(frame_state) = state.hyprland_frames.get_mut(frame) else {
+            return;
+        };
+
+        match event {
+            Event::Buffer {
+                format,
+                width,
+                height,
+                stride,
+            } => {
+                tracing::debug!(
+                    "Toplevel export buffer format: {:?}, size: {}x{}, stride: {}",
+                    format,
+                    width,
+                    height,
+                    stride
+                );
+
+                let WEnum::Value(format) = format else { return };
+
+                if !pixel_format::is_supported(format) {
+                    return;
+                }
+
+                let _ = frame_state.set_buffer_details(width, height, stride, format);
+            }
+            Event::BufferDone => match frame_state.create_buffer(&mut state.pool) {
+                // windows on other workspaces are never damaged, so don't wait for it
+                Ok(buffer) => frame.copy(buffer.wl_buffer(), 1),
+                Err(err) => {
+                    tracing::error!("{}", err);
+                    state.hyprland_frames.remove(frame);
+                    frame.destroy();
+                }
+            },
+            Event::Flags { flags } => {
+                use hyprland_toplevel_export_frame_v1::Flags;
+
+                match flags {
+                    WEnum::Value(flags) => {
+                        frame_state.y_invert = flags.contains(Flags::YInvert);
+                    }
+                    WEnum::Unknown(flag) => warn!("Unknown toplevel export flag: {}", flag),
+                };
+            }
+            Event::Ready { .. } => {
+                if let Some(event) = state
+                    .hyprland_frames
+                    .remove(frame)
+                    .and_then(ScreencopyFrameState::into_done_event)
+                {
+                    state.send_event(event);
+                }
+                frame.destroy();
+            }
+            Event::Failed => {
+                tracing::warn!("Toplevel export failed");
+                state.hyprland_frames.remove(frame);
+                frame.destroy();
+            }
+
+            // the whole window is copied every time
+            Event::Damage { .. } => {}
+
+            // LinuxDmabuf is a possible perf enhancement that can be explored in the future
+            Event::LinuxDmabuf { .. } => {}
+        }
     }
 }
@@ -1,6 +1,6 @@
 use anyhow::bail;
 use smithay_client_toolkit::{
-    reexports::client::protocol::wl_shm::Format,
+    reexports::client::protocol::wl_output::Transform,
     seat::{
         keyboard::{KeyEvent, Keysym, Modifiers},
         pointer::{PointerEvent, PointerEventKind},
@@ -9,18 +9,52 @@ use smithay_client_toolkit::{
     shm::slot::Buffer,
 };
 
+use crate::pixel_format::FrameLayout;
+
+/// Window states that are shown in the switcher
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ToplevelState {
+    pub minimized: bool,
+    pub maximized: bool,
+    pub fullscreen: bool,
+}
+
+/// A shm buffer that a window has been captured into
+#[derive(Debug)]
+pub struct CapturedBuffer {
+    pub buffer: Buffer,
+    pub layout: FrameLayout,
+}
+
+/// Part of a window that was captured from a single output
+#[derive(Debug)]
+pub struct CapturePiece {
+    pub buffer: CapturedBuffer,
+    /// Position and size of the piece within the window, in logical coordinates
+    pub region: (i32, i32, i32, i32),
+    /// Transform of the output the piece was captured from
+    pub transform: Transform,
+}
+
 #[derive(Debug)]
 pub enum WaylandClientEvent {
     LayerShellConfigure(LayerSurfaceConfigure),
     Egui(Vec<egui::Event>),
     ModifierChange,
     PaintRequest,
+    /// The scale our surface should be rendered at has changed
+    ScaleChanged(f64),
     TopLevelAdded(u32),
     TopLevelActivated(u32),
     TopLevelTitleUpdate(u32, String),
     TopLevelAppIdUpdate(u32, String),
+    TopLevelStateUpdate(u32, ToplevelState),
+    /// The window is now a child of another window, like a dialog, or no longer has a parent
+    TopLevelParentUpdate(u32, Option<u32>),
     TopLevelRemoved(u32),
-    ScreencopyDone(u32, Buffer, Format),
+    ScreencopyDone(u32, CapturedBuffer),
+    /// A window captured from one or more outputs, along with its logical size
+    ScreencopyRegionsDone(u32, (i32, i32), Vec<CapturePiece>),
 }
 
 impl WaylandClientEvent {
@@ -97,8 +131,22 @@ impl WaylandClientEvent {
             Keysym::Left => egui::Key::ArrowLeft,
             Keysym::Right => egui::Key::ArrowRight,
             Keysym::Tab | Keysym::ISO_Left_Tab => egui::Key::Tab,
-            Keysym::Return => egui::Key::Enter,
-            _ => bail!("keyboard event not mapped"),
+            Keysym::Return | Keysym::KP_Enter => egui::Key::Enter,
+            Keysym::Escape => egui::Key::Escape,
+            Keysym::Home | Keysym::KP_Home => egui::Key::Home,
+            Keysym::End | Keysym::KP_End => egui::Key::End,
+            Keysym::Page_Up | Keysym::KP_Page_Up => egui::Key::PageUp,
+            Keysym::Page_Down | Keysym::KP_Page_Down => egui::Key::PageDown,
+            Keysym::BackSpace => egui::Key::Backspace,
+            // typed text is used to search for windows
+            _ => match key_event.utf8 {
+                Some(text)
+                    if pressed && !text.is_empty() && !text.chars().any(char::is_control) =>
+                {
+                    return Ok(Self::Egui(vec![egui::Event::Text(text)]));
+                }
+                _ => bail!("keyboard event not mapped"),
+            },
         };
 
         let event = egui::Event::Key {
@@ -0,0 +1,155 @@
+use anyhow::Result;
+use regex::{Regex, RegexBuilder};
+
+use crate::ipc::WindowSelector;
+
+/// Upper bound on the memory a pattern from the command line may compile to
+const PATTERN_SIZE_LIMIT: usize = 1 << 20;
+
+/// A compiled [`WindowSelector`] used to select windows from the command line
+#[derive(Debug)]
+pub enum WindowMatcher {
+    Id(u32),
+    AppId(Regex),
+    Title(Regex),
+}
+
+impl WindowMatcher {
+    pub fn new(selector: &WindowSelector) -> Result<Self> {
+        Ok(match selector {
+            WindowSelector::Id(id) => Self::Id(*id),
+            WindowSelector::AppId(glob) => Self::AppId(build_regex(&glob_to_regex(glob))?),
+            WindowSelector::Title(pattern) => Self::Title(build_regex(pattern)?),
+        })
+    }
+
+    pub fn matches(&self, id: u32, app_id: &str, title: &str) -> bool {
+        match self {
+            Self::Id(expected) => id == *expected,
+            Self::AppId(regex) => regex.is_match(app_id),
+            Self::Title(regex) => regex.is_match(title),
+        }
+    }
+}
+
+fn build_regex(pattern: &str) -> Result<Regex> {
+    Ok(RegexBuilder::new(pattern)
+        .size_limit(PATTERN_SIZE_LIMIT)
+        .dfa_size_limit(PATTERN_SIZE_LIMIT)
+        .build()?)
+}
+
+/// Translates a shell style wildcard pattern into an anchored regex: `*` matches any run of
+/// characters, `?` a single character and `[...]` or `[!...]` a character class.
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' if chars.clone().skip(1).any(|c| c == ']') => {
+                regex.push('[');
+                if chars.next_if_eq(&'!').is_some() {
+                    regex.push('^');
+                }
+                // a `]` right after the opening bracket is part of the class
+                let mut first = true;
+                for c in chars.by_ref() {
+                    if c == ']' && !first {
+                        break;
+                    }
+                    first = false;
+                    match c {
+                        '-' => regex.push('-'),
+                        c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+                    }
+                }
+                regex.push(']');
+            }
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    fn app_id(glob: &str) -> WindowMatcher {
+        WindowMatcher::new(&WindowSelector::AppId(glob.into())).unwrap()
+    }
+
+    fn title(pattern: &str) -> WindowMatcher {
+        WindowMatcher::new(&WindowSelector::Title(pattern.into())).unwrap()
+    }
+
+    #[test]
+    fn id() {
+        let matcher = WindowMatcher::new(&WindowSelector::Id(7)).unwrap();
+        assert!(matcher.matches(7, "", ""));
+        assert!(!matcher.matches(8, "", ""));
+    }
+
+    #[test]
+    fn glob_wildcards() {
+        assert!(app_id("*firefox*").matches(0, "org.mozilla.firefox", ""));
+        assert!(app_id("foo?").matches(0, "foot", ""));
+        assert!(!app_id("foo?").matches(0, "foo", ""));
+        // the whole app id has to match
+        assert!(!app_id("foot").matches(0, "footclient", ""));
+    }
+
+    #[test]
+    fn glob_escapes_regex_syntax() {
+        assert!(app_id("org.kde.*").matches(0, "org.kde.dolphin", ""));
+        assert!(!app_id("org.kde.*").matches(0, "orgxkde.dolphin", ""));
+        assert!(app_id("a+b(c)").matches(0, "a+b(c)", ""));
+        assert!(app_id("[").matches(0, "[", ""));
+    }
+
+    #[test]
+    fn glob_classes() {
+        assert!(app_id("term[0-9]").matches(0, "term3", ""));
+        assert!(!app_id("term[0-9]").matches(0, "terma", ""));
+        assert!(app_id("term[!0-9]").matches(0, "terma", ""));
+        assert!(!app_id("term[!0-9]").matches(0, "term3", ""));
+        assert!(app_id("[]a]").matches(0, "]", ""));
+        assert!(app_id("[^]").matches(0, "^", ""));
+    }
+
+    #[test]
+    fn title_regex() {
+        assert!(title("README").matches(0, "", "README.md - Code"));
+        assert!(!title("^Code").matches(0, "", "README.md - Code"));
+        assert!(!title("readme").matches(0, "", "README.md - Code"));
+        assert!(title("(?i)readme").matches(0, "", "README.md - Code"));
+    }
+
+    #[test]
+    fn case_insensitive_keeps_negated_classes() {
+        assert!(title(r"(?i)^\D+$").matches(0, "", "Terminal"));
+        assert!(!title(r"(?i)^\D+$").matches(0, "", "1234"));
+        assert!(title(r"(?i)^\S+$").matches(0, "", "Term"));
+        assert!(!title(r"(?i)^\W+$").matches(0, "", "Term"));
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        assert!(WindowMatcher::new(&WindowSelector::Title("(unclosed".into())).is_err());
+        assert!(WindowMatcher::new(&WindowSelector::Title("a{1000}{1000}".into())).is_err());
+    }
+
+    #[test]
+    fn no_catastrophic_backtracking() {
+        let text = "a".repeat(10_000);
+        let started = Instant::now();
+        assert!(!title("(a*)*b").matches(0, "", &text));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}